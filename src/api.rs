@@ -0,0 +1,155 @@
+use reqwest::Client;
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::{get, routes, Route, State};
+
+use crate::config::Config;
+use crate::retrieval::{retrieve_source_file, retrieve_wiki_page_listing, ContentError};
+use crate::scraper::extract_links;
+use crate::wiki_page::WikiPageRef;
+use crate::{content_to_html, github_wiki_url_encoded, wiki_page_name};
+
+type ApiResult<T> = Result<Json<T>, status::Custom<Json<ApiError>>>;
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ApiError {
+    error: String,
+}
+
+fn api_error(error: ContentError) -> status::Custom<Json<ApiError>> {
+    let status = match error {
+        ContentError::NotFound => Status::NotFound,
+        ContentError::TooMayRequests => Status::TooManyRequests,
+        ContentError::Decommissioned => Status::Gone,
+        ContentError::OtherError(_) => Status::InternalServerError,
+    };
+    status::Custom(
+        status,
+        Json(ApiError {
+            error: error.to_string(),
+        }),
+    )
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PageResponse {
+    account: String,
+    repository: String,
+    page: String,
+    title: String,
+    /// Markup language of `source`, `github_html` if GitHub's rendering had to be used.
+    format: &'static str,
+    source: String,
+    html: String,
+    original_url: String,
+    /// Link targets in `html`, after rewriting for the mirror.
+    links: Vec<String>,
+}
+
+#[get("/<account>/<repository>/wiki")]
+async fn api_home(
+    account: &str,
+    repository: &str,
+    client: &State<Client>,
+    config: &State<Config>,
+) -> ApiResult<PageResponse> {
+    api_page(account, repository, "Home", client, config).await
+}
+
+#[get("/<account>/<repository>/wiki/<page>")]
+async fn api_page(
+    account: &str,
+    repository: &str,
+    page: &str,
+    client: &State<Client>,
+    config: &State<Config>,
+) -> ApiResult<PageResponse> {
+    let content = retrieve_source_file(account, repository, page, client)
+        .await
+        .map_err(api_error)?;
+
+    let format = content.format();
+    let source = content.source().to_string();
+    // The same HTML the mirror shows.
+    let html = content_to_html(content, account, repository, &config.render_options());
+
+    Ok(Json(PageResponse {
+        account: account.to_string(),
+        repository: repository.to_string(),
+        page: page.to_string(),
+        title: wiki_page_name(account, repository, page),
+        format,
+        source,
+        links: extract_links(&html),
+        html,
+        original_url: github_wiki_url_encoded(account, repository, page),
+    }))
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PageListEntry {
+    #[serde(flatten)]
+    page: WikiPageRef,
+    last_updated: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PagesResponse {
+    account: String,
+    repository: String,
+    pages: Vec<PageListEntry>,
+}
+
+#[get("/<account>/<repository>/pages")]
+async fn api_pages(
+    account: &str,
+    repository: &str,
+    client: &State<Client>,
+) -> ApiResult<PagesResponse> {
+    let mut listing = retrieve_wiki_page_listing(account, repository, client)
+        .await
+        .map_err(api_error)?;
+
+    let pages = listing
+        .pages
+        .into_iter()
+        .map(|page| PageListEntry {
+            last_updated: listing.last_updated.remove(&page.slug),
+            page,
+        })
+        .collect();
+
+    Ok(Json(PagesResponse {
+        account: account.to_string(),
+        repository: repository.to_string(),
+        pages,
+    }))
+}
+
+pub fn api_routes() -> Vec<Route> {
+    routes![api_home, api_page, api_pages]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_statuses() {
+        assert_eq!(api_error(ContentError::NotFound).0, Status::NotFound);
+        assert_eq!(
+            api_error(ContentError::TooMayRequests).0,
+            Status::TooManyRequests
+        );
+        assert_eq!(api_error(ContentError::Decommissioned).0, Status::Gone);
+        let error = api_error(ContentError::OtherError("Remote: 500".to_string()));
+        assert_eq!(error.0, Status::InternalServerError);
+        assert_eq!(error.1.error, "Remote: 500");
+    }
+}
@@ -0,0 +1,586 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+use std::sync::LazyLock;
+
+use askama::Template;
+use lol_html::{element, html_content::Element, HtmlRewriter, Settings};
+use percent_encoding::percent_decode_str;
+use rocket::time::OffsetDateTime;
+use scraper::{ElementRef, Html, Node, Selector};
+use thiserror::Error;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::export::WikiSources;
+use crate::retrieval::Content;
+use crate::scraper::{escape_html, extract_links, RenderOptions};
+use crate::toc::content_id;
+use crate::wiki_page::WikiPageRef;
+use crate::{content_to_html, github_wiki_url_encoded, wiki_page_name};
+
+// Elements XHTML has to close with `/>`.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+#[derive(Debug, Error)]
+pub enum BookError {
+    #[error("template render failed: {0}")]
+    Template(#[from] askama::Error),
+    #[error("could not write EPUB: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("could not write EPUB: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A wiki page as a chapter of a book.
+pub struct Chapter {
+    /// Anchor or file name stem, e.g. `chapter-3`.
+    pub id: String,
+    /// Page slug, percent-decoded.
+    pub slug: String,
+    pub title: String,
+    /// Page HTML as rendered for the mirror.
+    pub html: String,
+}
+
+/// Page slug and `#anchor` of a mirror link to a page of `account/repository`.
+fn mirror_page_target<'a>(
+    href: &'a str,
+    account: &str,
+    repository: &str,
+) -> Option<(String, Option<&'a str>)> {
+    let rest = href.strip_prefix(&format!("/m/{account}/{repository}/wiki"))?;
+    let (path, anchor) = match rest.split_once('#') {
+        Some((path, anchor)) => (path, Some(anchor)),
+        None => (rest, None),
+    };
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    let slug = match path {
+        "" | "/" => "Home",
+        _ => path.strip_prefix('/')?,
+    };
+    Some((
+        percent_decode_str(slug).decode_utf8_lossy().into_owned(),
+        anchor,
+    ))
+}
+
+/// Slugs of this wiki's pages in the order `_Sidebar` links to them.
+fn sidebar_slugs(sidebar_html: &str, account: &str, repository: &str) -> Vec<String> {
+    let mut slugs: Vec<String> = Vec::new();
+    for href in extract_links(sidebar_html) {
+        if let Some((slug, _)) = mirror_page_target(&href, account, repository) {
+            if !slugs.contains(&slug) {
+                slugs.push(slug);
+            }
+        }
+    }
+    slugs
+}
+
+/// Pages in sidebar order, then those the sidebar leaves out in listing order.
+fn book_order<'a>(pages: &'a [WikiPageRef], sidebar: &[String]) -> Vec<&'a WikiPageRef> {
+    let slug = |page: &WikiPageRef| {
+        percent_decode_str(&page.slug)
+            .decode_utf8_lossy()
+            .into_owned()
+    };
+
+    let mut ordered: Vec<&WikiPageRef> = sidebar
+        .iter()
+        .filter_map(|wanted| pages.iter().find(|page| slug(page) == *wanted))
+        .collect();
+    for page in pages {
+        if !ordered.contains(&page) {
+            ordered.push(page);
+        }
+    }
+    ordered
+}
+
+/// Every page of a wiki as chapters, ordered by `_Sidebar` when the wiki has one.
+pub fn book_chapters(sources: &WikiSources, options: &RenderOptions) -> Vec<Chapter> {
+    let (account, repository) = (sources.account.as_str(), sources.repository.as_str());
+    let sidebar = sources.sidebar.clone().map_or_else(Vec::new, |content| {
+        sidebar_slugs(
+            &content_to_html(content, account, repository, options),
+            account,
+            repository,
+        )
+    });
+    let contents: HashMap<&str, &Content> = sources
+        .pages
+        .iter()
+        .zip(&sources.contents)
+        .filter_map(|(page, content)| Some((page.slug.as_str(), content.as_ref()?)))
+        .collect();
+
+    book_order(&sources.pages, &sidebar)
+        .into_iter()
+        // Listed but gone by now.
+        .filter_map(|page| Some((page, *contents.get(page.slug.as_str())?)))
+        .enumerate()
+        .map(|(index, (page, content))| {
+            let slug = percent_decode_str(&page.slug)
+                .decode_utf8_lossy()
+                .into_owned();
+            Chapter {
+                id: format!("chapter-{}", index + 1),
+                title: wiki_page_name(account, repository, &slug),
+                slug,
+                html: content_to_html(content.clone(), account, repository, options),
+            }
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BookFormat {
+    SinglePage,
+    Epub,
+}
+
+/// Link to `anchor` in a chapter, or to the chapter itself.
+fn chapter_href(format: BookFormat, chapter: &str, anchor: Option<&str>) -> String {
+    let file = match format {
+        BookFormat::SinglePage => String::new(),
+        BookFormat::Epub => format!("{chapter}.xhtml"),
+    };
+    match anchor {
+        Some(anchor) if !anchor.is_empty() => {
+            format!("{file}#{chapter}--{}", content_id(anchor))
+        }
+        _ if file.is_empty() => format!("#{chapter}"),
+        _ => file,
+    }
+}
+
+struct BookLinks<'a> {
+    account: &'a str,
+    repository: &'a str,
+    base_url: &'a str,
+    format: BookFormat,
+    /// Decoded page slug to chapter id.
+    chapters: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> BookLinks<'a> {
+    fn new(
+        chapters: &'a [Chapter],
+        account: &'a str,
+        repository: &'a str,
+        base_url: &'a str,
+        format: BookFormat,
+    ) -> Self {
+        Self {
+            account,
+            repository,
+            base_url,
+            format,
+            chapters: chapters
+                .iter()
+                .map(|chapter| (chapter.slug.as_str(), chapter.id.as_str()))
+                .collect(),
+        }
+    }
+
+    fn href(&self, chapter: &str, href: &str) -> Option<String> {
+        if let Some(anchor) = href.strip_prefix('#') {
+            // Ids are prefixed with their chapter so they stay unique in the book.
+            return Some(format!("#{chapter}--{anchor}"));
+        }
+        if !href.starts_with('/') || href.starts_with("//") {
+            return None;
+        }
+        match mirror_page_target(href, self.account, self.repository)
+            .and_then(|(slug, anchor)| Some((*self.chapters.get(slug.as_str())?, anchor)))
+        {
+            Some((target, anchor)) => Some(chapter_href(self.format, target, anchor)),
+            None => Some(format!("{}{href}", self.base_url)),
+        }
+    }
+
+    /// Chapter HTML with ids prefixed by the chapter and links pointing within the book.
+    fn rewrite(&self, chapter: &Chapter) -> String {
+        let prefix = |el: &mut Element, attribute: &str| {
+            if let Some(value) = el.get_attribute(attribute) {
+                el.set_attribute(attribute, &format!("{}--{value}", chapter.id))
+                    .unwrap();
+            }
+        };
+
+        let mut output = Vec::new();
+        let mut rewriter = HtmlRewriter::new(
+            Settings {
+                element_content_handlers: vec![
+                    element!("[id], a[name]", |el: &mut Element| {
+                        prefix(el, "id");
+                        prefix(el, "name");
+                        Ok(())
+                    }),
+                    element!("a[href]", |el: &mut Element| {
+                        if let Some(href) = el
+                            .get_attribute("href")
+                            .and_then(|href| self.href(&chapter.id, &href))
+                        {
+                            el.set_attribute("href", &href).unwrap();
+                        }
+                        Ok(())
+                    }),
+                    // Proxied images are still fetched from the mirror.
+                    element!("img[src^='/']:not([src^='//'])", |el: &mut Element| {
+                        let src = el.get_attribute("src").unwrap();
+                        el.set_attribute("src", &format!("{}{src}", self.base_url))
+                            .unwrap();
+                        Ok(())
+                    }),
+                ],
+                ..Settings::default()
+            },
+            |c: &[u8]| output.extend_from_slice(c),
+        );
+
+        rewriter.write(chapter.html.as_bytes()).unwrap();
+        rewriter.end().unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+}
+
+fn write_xhtml(element: ElementRef, xhtml: &mut String) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => xhtml.push_str(&escape_html(text)),
+            Node::Element(el) => {
+                let name = el.name();
+                xhtml.push('<');
+                xhtml.push_str(name);
+                for (attribute, value) in el.attrs() {
+                    xhtml.push_str(&format!(" {attribute}=\"{}\"", escape_html(value)));
+                }
+                if name == "math" && el.attr("xmlns").is_none() {
+                    xhtml.push_str(" xmlns=\"http://www.w3.org/1998/Math/MathML\"");
+                }
+                if VOID_ELEMENTS.contains(&name) {
+                    xhtml.push_str("/>");
+                    continue;
+                }
+                xhtml.push('>');
+                if let Some(child) = ElementRef::wrap(child) {
+                    write_xhtml(child, xhtml);
+                }
+                xhtml.push_str(&format!("</{name}>"));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Well-formed XHTML for an HTML fragment, as EPUB requires.
+fn to_xhtml(html: &str) -> String {
+    let mut xhtml = String::new();
+    write_xhtml(Html::parse_fragment(html).root_element(), &mut xhtml);
+    xhtml
+}
+
+struct BookChapter<'a> {
+    id: &'a str,
+    title: &'a str,
+    html: String,
+    /// Space-separated EPUB manifest properties of the chapter, if any.
+    properties: String,
+}
+
+static SRC_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse("[src]").expect("src selector should compile"));
+
+/// Manifest properties EPUB 3 requires for a chapter's XHTML.
+fn manifest_properties(xhtml: &str) -> String {
+    let mut properties = Vec::new();
+    if xhtml.contains("<math") {
+        properties.push("mathml");
+    }
+    // Images, embedded videos and other media that readers have to fetch.
+    let remote = Html::parse_fragment(xhtml)
+        .select(&SRC_SELECTOR)
+        .filter_map(|element| element.value().attr("src"))
+        .any(|src| {
+            src.starts_with("https://") || src.starts_with("http://") || src.starts_with("//")
+        });
+    if remote {
+        properties.push("remote-resources");
+    }
+    properties.join(" ")
+}
+
+fn book_title(account: &str, repository: &str) -> String {
+    format!("{account}/{repository} GitHub Wiki")
+}
+
+#[derive(Template)]
+#[template(path = "book.html")]
+struct BookTemplate<'a> {
+    title: String,
+    original_url: String,
+    chapters: Vec<BookChapter<'a>>,
+}
+
+/// The whole wiki as one HTML document, with links between pages as anchors.
+pub fn single_page_html(
+    chapters: &[Chapter],
+    account: &str,
+    repository: &str,
+    base_url: &str,
+) -> Result<String, BookError> {
+    let links = BookLinks::new(
+        chapters,
+        account,
+        repository,
+        base_url,
+        BookFormat::SinglePage,
+    );
+    let template = BookTemplate {
+        title: book_title(account, repository),
+        original_url: github_wiki_url_encoded(account, repository, "Home"),
+        chapters: chapters
+            .iter()
+            .map(|chapter| BookChapter {
+                id: &chapter.id,
+                title: &chapter.title,
+                html: links.rewrite(chapter),
+                properties: String::new(),
+            })
+            .collect(),
+    };
+    Ok(template.render()?)
+}
+
+#[derive(Template)]
+#[template(path = "epub/package.opf", escape = "html")]
+struct EpubPackageTemplate<'a> {
+    identifier: String,
+    title: String,
+    /// `CCYY-MM-DDThh:mm:ssZ`, as EPUB requires.
+    modified: String,
+    chapters: &'a [BookChapter<'a>],
+}
+
+#[derive(Template)]
+#[template(path = "epub/nav.xhtml", escape = "html")]
+struct EpubNavTemplate<'a> {
+    title: String,
+    chapters: &'a [BookChapter<'a>],
+}
+
+#[derive(Template)]
+#[template(path = "epub/chapter.xhtml", escape = "html")]
+struct EpubChapterTemplate<'a> {
+    chapter: &'a BookChapter<'a>,
+}
+
+/// The whole wiki as an EPUB 3 book, one chapter per page.
+pub fn epub(
+    chapters: &[Chapter],
+    account: &str,
+    repository: &str,
+    base_url: &str,
+) -> Result<Vec<u8>, BookError> {
+    let links = BookLinks::new(chapters, account, repository, base_url, BookFormat::Epub);
+    let chapters: Vec<BookChapter> = chapters
+        .iter()
+        .map(|chapter| {
+            let html = to_xhtml(&links.rewrite(chapter));
+            BookChapter {
+                id: &chapter.id,
+                title: &chapter.title,
+                properties: manifest_properties(&html),
+                html,
+            }
+        })
+        .collect();
+    let title = book_title(account, repository);
+    let now = OffsetDateTime::now_utc();
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    // Readers recognise EPUBs by this first, uncompressed file.
+    zip.start_file(
+        "mimetype",
+        SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    zip.write_all(b"application/epub+zip")?;
+
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file("META-INF/container.xml", options)?;
+    zip.write_all(include_bytes!("../templates/epub/container.xml"))?;
+
+    zip.start_file("OEBPS/package.opf", options)?;
+    let package = EpubPackageTemplate {
+        identifier: github_wiki_url_encoded(account, repository, "Home"),
+        title: title.clone(),
+        modified: format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            now.year(),
+            u8::from(now.month()),
+            now.day(),
+            now.hour(),
+            now.minute(),
+            now.second()
+        ),
+        chapters: &chapters,
+    };
+    zip.write_all(package.render()?.as_bytes())?;
+
+    zip.start_file("OEBPS/nav.xhtml", options)?;
+    let nav = EpubNavTemplate {
+        title,
+        chapters: &chapters,
+    };
+    zip.write_all(nav.render()?.as_bytes())?;
+
+    for chapter in &chapters {
+        zip.start_file(format!("OEBPS/{}.xhtml", chapter.id), options)?;
+        zip.write_all(EpubChapterTemplate { chapter }.render()?.as_bytes())?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(slug: &str) -> WikiPageRef {
+        WikiPageRef {
+            account: "a".to_string(),
+            repository: "r".to_string(),
+            slug: slug.to_string(),
+            title: slug.to_string(),
+        }
+    }
+
+    fn chapter(id: &str, slug: &str, html: &str) -> Chapter {
+        Chapter {
+            id: id.to_string(),
+            slug: slug.to_string(),
+            title: slug.to_string(),
+            html: html.to_string(),
+        }
+    }
+
+    #[test]
+    fn sidebar_order() {
+        let sidebar = sidebar_slugs(
+            "<ul><li><a href=\"/m/a/r/wiki/Setup#linux\">Setup</a></li><li><a href=\"https://example.com\">Elsewhere</a></li><li><a href=\"/m/a/r/wiki/Caf%C3%A9\">Café</a></li><li><a href=\"/m/a/r/wiki/Setup\">Again</a></li><li><a href=\"/m/a/r/wiki/Missing\">Missing</a></li></ul>",
+            "a",
+            "r",
+        );
+        assert_eq!(sidebar, ["Setup", "Café", "Missing"]);
+
+        let pages = [page("Home"), page("Caf%C3%A9"), page("FAQ"), page("Setup")];
+        let slugs: Vec<&str> = book_order(&pages, &sidebar)
+            .iter()
+            .map(|page| page.slug.as_str())
+            .collect();
+        assert_eq!(slugs, ["Setup", "Caf%C3%A9", "Home", "FAQ"]);
+    }
+
+    #[test]
+    fn links_within_book() {
+        let chapters = [
+            chapter(
+                "chapter-1",
+                "Home",
+                "<h2 id=\"usage\"><a href=\"#usage\" class=\"anchor\"></a>Usage</h2><p><a href=\"/m/a/r/wiki/Setup#linux\">Setup</a> <a href=\"/m/a/r/wiki/Gone\">Gone</a> <a href=\"https://example.org\">Out</a><img src=\"/img?url=x\"></p>",
+            ),
+            chapter("chapter-2", "Setup", "<p><a href=\"/m/a/r/wiki\">Home</a></p>"),
+        ];
+
+        let links = BookLinks::new(
+            &chapters,
+            "a",
+            "r",
+            "https://example.com",
+            BookFormat::SinglePage,
+        );
+        assert_eq!(
+            links.rewrite(&chapters[0]),
+            "<h2 id=\"chapter-1--usage\"><a href=\"#chapter-1--usage\" class=\"anchor\"></a>Usage</h2><p><a href=\"#chapter-2--user-content-linux\">Setup</a> <a href=\"https://example.com/m/a/r/wiki/Gone\">Gone</a> <a href=\"https://example.org\">Out</a><img src=\"https://example.com/img?url=x\"></p>"
+        );
+        assert_eq!(
+            links.rewrite(&chapters[1]),
+            "<p><a href=\"#chapter-1\">Home</a></p>"
+        );
+
+        let links = BookLinks::new(&chapters, "a", "r", "https://example.com", BookFormat::Epub);
+        assert_eq!(
+            links.rewrite(&chapters[1]),
+            "<p><a href=\"chapter-1.xhtml\">Home</a></p>"
+        );
+        assert!(links
+            .rewrite(&chapters[0])
+            .contains("href=\"chapter-2.xhtml#chapter-2--user-content-linux\""));
+    }
+
+    #[test]
+    fn xhtml_serialization() {
+        assert_eq!(
+            to_xhtml("<p>a<br>b &amp; <img src=\"x.png\" alt='\"q\"'></p><ul><li><input type=\"checkbox\" checked disabled> done</ul><math><mi>x</mi></math><!-- gone -->"),
+            "<p>a<br/>b &amp; <img alt=\"&quot;q&quot;\" src=\"x.png\"/></p><ul><li><input checked=\"\" disabled=\"\" type=\"checkbox\"/> done</li></ul><math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mi>x</mi></math>"
+        );
+    }
+
+    #[test]
+    fn epub_package() {
+        let chapters = [chapter("chapter-1", "Home", "<p>Hello<br>world</p>")];
+        let book = epub(&chapters, "a", "r", "https://example.com").unwrap();
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(book)).unwrap();
+        let mimetype = archive.by_index(0).unwrap();
+        assert_eq!(mimetype.name().unwrap(), "mimetype");
+        assert_eq!(mimetype.compression(), CompressionMethod::Stored);
+        drop(mimetype);
+
+        let mut chapter = String::new();
+        std::io::Read::read_to_string(
+            &mut archive.by_name("OEBPS/chapter-1.xhtml").unwrap(),
+            &mut chapter,
+        )
+        .unwrap();
+        assert!(chapter.contains("<p>Hello<br/>world</p>"));
+        assert!(archive.by_name("OEBPS/nav.xhtml").is_ok());
+        assert!(archive.by_name("OEBPS/package.opf").is_ok());
+    }
+
+    #[test]
+    fn epub_manifest_properties() {
+        let chapters = [
+            chapter("chapter-1", "Home", "<p>Hello</p>"),
+            chapter(
+                "chapter-2",
+                "Math",
+                "<math><mi>x</mi></math><img src=\"/img?url=x\">",
+            ),
+            chapter(
+                "chapter-3",
+                "Video",
+                "<iframe src=\"https://www.youtube-nocookie.com/embed/x\"></iframe>",
+            ),
+        ];
+        let book = epub(&chapters, "a", "r", "https://example.com").unwrap();
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(book)).unwrap();
+        let mut package = String::new();
+        std::io::Read::read_to_string(
+            &mut archive.by_name("OEBPS/package.opf").unwrap(),
+            &mut package,
+        )
+        .unwrap();
+        assert!(package.contains(
+            "<item id=\"chapter-1\" href=\"chapter-1.xhtml\" media-type=\"application/xhtml+xml\"/>"
+        ));
+        assert!(package.contains("href=\"chapter-2.xhtml\" media-type=\"application/xhtml+xml\" properties=\"mathml remote-resources\"/>"));
+        assert!(package.contains("href=\"chapter-3.xhtml\" media-type=\"application/xhtml+xml\" properties=\"remote-resources\"/>"));
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Small in-memory cache with a fixed time-to-live and a capacity bound.
+///
+/// Good enough for a single machine; entries are lost on restart.
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    /// Most total weight of the entries; each entry weighs 1 unless built [`Self::with_weigher`].
+    capacity: usize,
+    weigh: fn(&V) -> usize,
+    entries: Mutex<Entries<K, V>>,
+}
+
+struct Entries<K, V> {
+    map: HashMap<K, (Instant, V)>,
+    /// Keys oldest first, so expiry and eviction never scan the map. Keys inserted again
+    /// leave a stale record behind, skipped when it reaches the front.
+    order: VecDeque<(Instant, K)>,
+    weight: usize,
+}
+
+impl<K: Eq + Hash, V> Entries<K, V> {
+    fn is_current(&self, inserted: Instant, key: &K) -> bool {
+        self.map.get(key).is_some_and(|(at, _)| *at == inserted)
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self::with_weigher(ttl, capacity, |_| 1)
+    }
+
+    /// A cache bounded by the total `weigh`t of its entries, e.g. their size in bytes.
+    pub fn with_weigher(ttl: Duration, capacity: usize, weigh: fn(&V) -> usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            weigh,
+            entries: Mutex::new(Entries {
+                map: HashMap::new(),
+                order: VecDeque::new(),
+                weight: 0,
+            }),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries
+            .map
+            .get(key)
+            .filter(|(inserted, _)| inserted.elapsed() < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let weight = (self.weigh)(&value);
+        if weight > self.capacity {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((_, replaced)) = entries.map.remove(&key) {
+            entries.weight -= (self.weigh)(&replaced);
+        }
+        // Drop expired entries, then the oldest live ones until the new one fits.
+        while let Some((inserted, oldest)) = entries.order.front() {
+            let (inserted, current) = (*inserted, entries.is_current(*inserted, oldest));
+            if current && inserted.elapsed() < self.ttl && entries.weight + weight <= self.capacity
+            {
+                break;
+            }
+            let (_, oldest) = entries.order.pop_front().expect("front was just seen");
+            if current {
+                if let Some((_, removed)) = entries.map.remove(&oldest) {
+                    entries.weight -= (self.weigh)(&removed);
+                }
+            }
+        }
+        // Keep stale records of often replaced keys from piling up.
+        if entries.order.len() > 2 * entries.map.len() + 64 {
+            let Entries { map, order, .. } = &mut *entries;
+            order.retain(|(inserted, key)| map.get(key).is_some_and(|(at, _)| at == inserted));
+        }
+
+        let now = Instant::now();
+        entries.order.push_back((now, key.clone()));
+        entries.map.insert(key, (now, value));
+        entries.weight += weight;
+    }
+
+    /// Keys of all entries that have not expired yet.
+    pub fn keys(&self) -> Vec<K> {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries
+            .map
+            .iter()
+            .filter(|(_, (inserted, _))| inserted.elapsed() < self.ttl)
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expires_entries() {
+        let cache = TtlCache::new(Duration::ZERO, 10);
+        cache.insert("a", 1);
+        assert_eq!(cache.get(&"a"), None);
+        assert!(cache.keys().is_empty());
+    }
+
+    #[test]
+    fn evicts_oldest_when_full() {
+        let cache = TtlCache::new(Duration::from_secs(60), 2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("c", 3);
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some(2));
+        assert_eq!(cache.get(&"c"), Some(3));
+    }
+
+    #[test]
+    fn replaced_entries_count_as_new() {
+        let cache = TtlCache::new(Duration::from_secs(60), 2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("a", 3);
+        cache.insert("c", 4);
+        assert_eq!(cache.get(&"a"), Some(3));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"c"), Some(4));
+    }
+
+    #[test]
+    fn bounds_total_weight() {
+        let cache =
+            TtlCache::with_weigher(Duration::from_secs(60), 10, |value: &String| value.len());
+        cache.insert("a", "aaaa".to_string());
+        cache.insert("b", "bbbb".to_string());
+        cache.insert("c", "cccc".to_string());
+        cache.insert("huge", "h".repeat(11));
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some("bbbb".to_string()));
+        assert_eq!(cache.get(&"c"), Some("cccc".to_string()));
+        assert_eq!(cache.get(&"huge"), None);
+    }
+}
@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use rocket::serde::Deserialize;
+
+use crate::content_store::ContentStore;
+use crate::page_meta::CanonicalStrategy;
+use crate::scraper::RenderOptions;
+
+/// Deployment settings, read from Rocket's configuration sources
+/// (`Rocket.toml` or `ROCKET_*` environment variables).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Config {
+    /// Public origin of this deployment, used for absolute URLs such as sitemap entries.
+    #[serde(default = "default_public_base_url")]
+    pub public_base_url: String,
+    /// Serve images of mirrored pages through `/img` instead of hotlinking GitHub.
+    #[serde(default)]
+    pub image_proxy: bool,
+    /// Syntax highlight code blocks on mirrored pages.
+    #[serde(default = "default_highlight_code")]
+    pub highlight_code: bool,
+    /// What mirror pages declare as their canonical URL.
+    #[serde(default)]
+    pub canonical: CanonicalStrategy,
+    /// Offer whole wikis as downloads at `/m/<account>/<repository>/export.zip` and `book.*`.
+    #[serde(default)]
+    pub serve_exports: bool,
+    /// Directory the search index is kept in. Without one it only lives in memory.
+    #[serde(default)]
+    pub search_index_path: Option<PathBuf>,
+    /// Directory to keep the last fetched copy of each page in, served when GitHub fails.
+    #[serde(default)]
+    pub content_store_path: Option<PathBuf>,
+    /// File or URL listing wikis to fetch ahead of crawlers, see [`crate::warm_up::parse_seed`].
+    #[serde(default)]
+    pub warm_up_seed: Option<String>,
+    /// How many GitHub requests warming up may make per minute.
+    #[serde(default = "default_warm_up_requests_per_minute")]
+    pub warm_up_requests_per_minute: u32,
+    /// Hours between background warm-up runs.
+    #[serde(default = "default_warm_up_period_hours")]
+    pub warm_up_period_hours: u64,
+}
+
+fn default_public_base_url() -> String {
+    "https://github-wiki-see.page".to_string()
+}
+
+fn default_highlight_code() -> bool {
+    true
+}
+
+fn default_warm_up_requests_per_minute() -> u32 {
+    30
+}
+
+fn default_warm_up_period_hours() -> u64 {
+    24
+}
+
+impl Config {
+    /// `public_base_url` without any trailing slash.
+    pub fn base_url(&self) -> &str {
+        self.public_base_url.trim_end_matches('/')
+    }
+
+    pub fn content_store(&self) -> Option<ContentStore> {
+        self.content_store_path.clone().map(ContentStore::new)
+    }
+
+    pub fn render_options(&self) -> RenderOptions {
+        RenderOptions {
+            proxy_images: self.image_proxy,
+            highlight_code: self.highlight_code,
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::io;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rocket::serde::json::{from_str, to_string};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::time::OffsetDateTime;
+use rocket::warn;
+use tokio::task::JoinHandle;
+
+use crate::cache::TtlCache;
+
+// Pages are viewed far more often than they change; don't rewrite them on every view.
+static RECENTLY_STORED: LazyLock<TtlCache<PathBuf, ()>> =
+    LazyLock::new(|| TtlCache::new(Duration::from_secs(10 * 60), 4096));
+
+/// Longest encoded name kept as is, leaving room for `.json.partial` within the usual
+/// 255 byte limit on file names.
+const MAX_ENCODED_NAME: usize = 240;
+/// How much of a longer name is kept in front of its hash.
+const SHORTENED_NAME_PREFIX: usize = 200;
+
+/// 64-bit FNV-1a, which unlike `std`'s hashers is the same in every Rust release.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// `part` as a file name: percent-encoded, and shortened with a hash when that is too long.
+fn file_name(part: &str) -> String {
+    let encoded = utf8_percent_encode(part, NON_ALPHANUMERIC).to_string();
+    if encoded.len() <= MAX_ENCODED_NAME {
+        return encoded;
+    }
+    // Encoded names are ASCII, and never contain `~` themselves.
+    format!(
+        "{}~{:016x}",
+        &encoded[..SHORTENED_NAME_PREFIX],
+        fnv1a(part.as_bytes())
+    )
+}
+
+/// The last successfully fetched copy of a page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Snapshot {
+    pub title: String,
+    /// Markup of `source`, as [`crate::retrieval::Content::format`] names it.
+    pub format: String,
+    pub source: String,
+    /// Sanitized page HTML, before heading anchors.
+    pub html: String,
+    /// Unix time the page was fetched.
+    pub fetched_at: u64,
+    /// Fetched by a warm-up rather than for a visitor.
+    #[serde(default)]
+    pub warmed: bool,
+}
+
+impl Snapshot {
+    pub fn new(title: String, format: &str, source: String, html: String) -> Self {
+        Self {
+            title,
+            format: format.to_string(),
+            source,
+            html,
+            fetched_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            warmed: false,
+        }
+    }
+
+    /// How long ago the page was fetched.
+    pub fn age(&self) -> Duration {
+        let fetched_at = UNIX_EPOCH + Duration::from_secs(self.fetched_at);
+        SystemTime::now()
+            .duration_since(fetched_at)
+            .unwrap_or_default()
+    }
+
+    /// When the page was fetched, e.g. `2024-03-01 14:05 UTC`.
+    pub fn fetched_date(&self) -> String {
+        let fetched_at = i64::try_from(self.fetched_at)
+            .ok()
+            .and_then(|seconds| OffsetDateTime::from_unix_timestamp(seconds).ok())
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH);
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+            fetched_at.year(),
+            u8::from(fetched_at.month()),
+            fetched_at.day(),
+            fetched_at.hour(),
+            fetched_at.minute()
+        )
+    }
+}
+
+/// Pages as last fetched, kept on disk to serve while GitHub cannot be reached.
+///
+/// Each page is a JSON file at `<root>/<account>/<repository>/<page>.json`, with every
+/// part percent-encoded so any page name is a safe file name.
+#[derive(Debug, Clone)]
+pub struct ContentStore {
+    root: PathBuf,
+}
+
+impl ContentStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, account: &str, repository: &str, page: &str) -> PathBuf {
+        // GitHub treats account and repository names case-insensitively.
+        self.root
+            .join(file_name(&account.to_lowercase()))
+            .join(file_name(&repository.to_lowercase()))
+            .join(format!("{}.json", file_name(page)))
+    }
+
+    pub fn load(&self, account: &str, repository: &str, page: &str) -> Option<Snapshot> {
+        let json = std::fs::read_to_string(self.path(account, repository, page)).ok()?;
+        from_str(&json).ok()
+    }
+
+    /// Replace the stored copy of a page, unless it was stored moments ago.
+    pub fn save(
+        &self,
+        account: &str,
+        repository: &str,
+        page: &str,
+        snapshot: &Snapshot,
+    ) -> io::Result<()> {
+        let path = self.path(account, repository, page);
+        if RECENTLY_STORED.get(&path).is_some() {
+            return Ok(());
+        }
+        let json = to_string(snapshot).map_err(io::Error::other)?;
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Readers never see a half written file.
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, json)?;
+        std::fs::rename(&partial, &path)?;
+
+        RECENTLY_STORED.insert(path, ());
+        Ok(())
+    }
+
+    /// [`ContentStore::save`] on a blocking thread, logging failures.
+    pub fn save_in_background(
+        self,
+        account: &str,
+        repository: &str,
+        page: &str,
+        snapshot: Snapshot,
+    ) -> JoinHandle<()> {
+        let (account, repository, page) = (
+            account.to_string(),
+            repository.to_string(),
+            page.to_string(),
+        );
+        tokio::task::spawn_blocking(move || {
+            if let Err(error) = self.save(&account, &repository, &page, &snapshot) {
+                warn!("Could not store {account}/{repository} page {page}: {error}");
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_snapshots() {
+        let root = std::env::temp_dir().join(format!("content-store-{}", std::process::id()));
+        let store = ContentStore::new(root.clone());
+        let snapshot = Snapshot {
+            title: "Setup".to_string(),
+            format: "markdown".to_string(),
+            source: "# Setup".to_string(),
+            html: "<h1>Setup</h1>".to_string(),
+            fetched_at: 1_709_301_900,
+            warmed: false,
+        };
+
+        assert_eq!(store.load("A", "R", "Setup"), None);
+        store.save("A", "R", "../Setup", &snapshot).unwrap();
+        store.save("A", "R", "Setup", &snapshot).unwrap();
+        assert_eq!(store.load("a", "r", "Setup"), Some(snapshot.clone()));
+        assert!(root.join("a/r/%2E%2E%2FSetup.json").exists());
+
+        let long = "Ü".repeat(100);
+        store.save("A", "R", &long, &snapshot).unwrap();
+        assert_eq!(store.load("A", "R", &long), Some(snapshot.clone()));
+        assert_ne!(file_name(&long), file_name(&"Ü".repeat(101)));
+        assert_eq!(file_name(&long).len(), SHORTENED_NAME_PREFIX + 17);
+        assert_eq!(snapshot.fetched_date(), "2024-03-01 14:05 UTC");
+        assert!(snapshot.age() > Duration::from_secs(365 * 24 * 60 * 60));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
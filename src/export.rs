@@ -0,0 +1,795 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use askama::Template;
+use futures::{stream, StreamExt, TryStreamExt};
+use lol_html::{element, html_content::Element, HtmlRewriter, Settings};
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use reqwest::Client;
+use rocket::http::{ContentType, Header, Status};
+use rocket::response::status;
+use rocket::{get, routes, Responder, Route, State};
+use thiserror::Error;
+use tokio::sync::Semaphore;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::book::{book_chapters, epub, single_page_html, BookError};
+use crate::cache::TtlCache;
+use crate::config::Config;
+use crate::retrieval::{
+    content_constructor_for_extension, retrieve_source_file, retrieve_wiki_page_listing,
+    wiki_index_content, Content, ContentError,
+};
+use crate::scraper::RenderOptions;
+use crate::toc::{add_heading_anchors, render_toc};
+use crate::wiki_page::{page_navigation, WikiPageRef};
+use crate::{
+    content_to_html, github_wiki_url_encoded, mirror_index_url, mirror_search_url,
+    wiki_breadcrumbs, wiki_page_name, wiki_page_title, MirrorTemplate, NON_ALPHANUMERIC_GH,
+};
+
+/// Pages fetched from GitHub at the same time.
+const CONCURRENT_PAGES: usize = 4;
+// Pages whose file would take this name get another, see `PageFiles`.
+const INDEX_FILE: &str = "_index.html";
+const BACKGROUND_FILE: &str = "callToAction.svg";
+/// Larger wikis take too long and too many GitHub requests to export on demand; use the CLI.
+const MAX_SERVED_EXPORT_PAGES: usize = 500;
+
+static EXPORT_CACHE: LazyLock<TtlCache<(String, String, ExportFormat), Vec<u8>>> =
+    LazyLock::new(|| TtlCache::new(Duration::from_secs(60 * 60), 16));
+/// Fetched wikis, so that each format of an export is rendered from the same fetch.
+static SOURCES_CACHE: LazyLock<TtlCache<(String, String), Arc<WikiSources>>> =
+    LazyLock::new(|| TtlCache::new(Duration::from_secs(60 * 60), 4));
+/// Served exports fetching a wiki at once, across all wikis and formats.
+static SERVED_EXPORTS: Semaphore = Semaphore::const_new(2);
+
+/// A file of an exported wiki, with a path relative to the export's root.
+pub struct ExportFile {
+    pub path: String,
+    pub contents: Vec<u8>,
+}
+
+/// Characters Windows refuses in file names. Slugs never contain path separators on
+/// GitHub, but don't let one escape the export either.
+const RESERVED_CHARACTERS: &[char] = &['/', '\\', '?', ':', '*', '"', '<', '>', '|'];
+
+// `CON`, `NUL`, `COM1` and the like name devices on Windows, whatever their extension.
+fn is_reserved_on_windows(stem: &str) -> bool {
+    let base = stem.split('.').next().unwrap_or(stem).to_uppercase();
+    match base.as_str() {
+        "CON" | "PRN" | "AUX" | "NUL" => true,
+        _ => {
+            (base.starts_with("COM") || base.starts_with("LPT"))
+                && base.len() == 4
+                && base.ends_with(|c: char| ('1'..='9').contains(&c))
+        }
+    }
+}
+
+/// Name of the exported file for a percent-decoded page name, safe on every common
+/// file system.
+fn page_file_name(page: &str) -> String {
+    let mut stem: String = page
+        .chars()
+        .map(|c| {
+            if c.is_control() || RESERVED_CHARACTERS.contains(&c) {
+                '-'
+            } else {
+                c
+            }
+        })
+        .collect();
+    // Windows drops trailing dots and spaces, which would merge the stem with `.html`.
+    let kept = stem.trim_end_matches(['.', ' ']).len();
+    let dropped = stem.len() - kept;
+    stem.truncate(kept);
+    stem.push_str(&"-".repeat(dropped));
+    if is_reserved_on_windows(&stem) {
+        stem.insert(0, '_');
+    }
+    format!("{stem}.html")
+}
+
+/// File names of the pages of an export.
+///
+/// Names stay unique even on file systems that ignore case, and never replace the index.
+pub struct PageFiles {
+    /// Percent-decoded page slug to file name.
+    names: HashMap<String, String>,
+}
+
+impl PageFiles {
+    pub fn new(pages: &[WikiPageRef]) -> Self {
+        let mut taken: HashSet<String> = [INDEX_FILE, BACKGROUND_FILE]
+            .iter()
+            .map(|file| file.to_lowercase())
+            .collect();
+        let mut names = HashMap::new();
+        let mut clashing = Vec::new();
+        for page in pages {
+            let page = percent_decode_str(&page.slug)
+                .decode_utf8_lossy()
+                .into_owned();
+            let name = page_file_name(&page);
+            // A page listed twice finds its name taken and is skipped below.
+            if taken.insert(name.to_lowercase()) {
+                names.insert(page, name);
+            } else {
+                clashing.push(page);
+            }
+        }
+        // Numbered only once every page had the chance to keep its own name.
+        for page in clashing {
+            if names.contains_key(&page) {
+                continue;
+            }
+            let name = (2..)
+                .map(|number| page_file_name(&format!("{page}-{number}")))
+                .find(|name| taken.insert(name.to_lowercase()))
+                .unwrap();
+            names.insert(page, name);
+        }
+        Self { names }
+    }
+
+    /// Name of the exported file for a page slug, e.g. `Getting-Started.html`.
+    fn name(&self, slug: &str) -> String {
+        let page = percent_decode_str(slug).decode_utf8_lossy();
+        match self.names.get(page.as_ref()) {
+            Some(name) => name.clone(),
+            // Links to pages outside the export keep the name they would have had.
+            None => page_file_name(&page),
+        }
+    }
+
+    /// Whether a page slug is one of the exported pages.
+    fn contains(&self, slug: &str) -> bool {
+        let page = percent_decode_str(slug).decode_utf8_lossy();
+        self.names.contains_key(page.as_ref())
+    }
+
+    /// Relative link for a page slug.
+    fn href(&self, slug: &str) -> String {
+        utf8_percent_encode(&self.name(slug), NON_ALPHANUMERIC_GH).to_string()
+    }
+}
+
+// Raw sources like `Home.md` are not part of the export.
+fn is_source_file(page: &str) -> bool {
+    page.rsplit_once('.')
+        .is_some_and(|(_, extension)| content_constructor_for_extension(extension).is_some())
+}
+
+/// Where a link on an exported page should point instead, if anywhere else.
+///
+/// Pages of the exported wiki become relative file links. Other paths on the mirror become
+/// absolute links to `base_url`.
+fn export_href(
+    href: &str,
+    account: &str,
+    repository: &str,
+    files: &PageFiles,
+    base_url: &str,
+) -> Option<String> {
+    if !href.starts_with('/') || href.starts_with("//") {
+        return None;
+    }
+    let Some(rest) = href.strip_prefix(&format!("/m/{account}/{repository}/")) else {
+        return Some(format!("{base_url}{href}"));
+    };
+    let path_end = rest.find(['?', '#']).unwrap_or(rest.len());
+    let (path, anchor) = (&rest[..path_end], &rest[path_end..]);
+    let anchor = anchor.find('#').map_or("", |start| &anchor[start..]);
+
+    match path {
+        "wiki" => Some(format!("{}{anchor}", files.href("Home"))),
+        "wiki_index" => Some(INDEX_FILE.to_string()),
+        _ => match path.strip_prefix("wiki/") {
+            // A page may be named like a source file, e.g. `Notes.md`.
+            Some(page) if files.contains(page) || (!page.is_empty() && !is_source_file(page)) => {
+                Some(format!("{}{anchor}", files.href(page)))
+            }
+            _ => Some(format!("{base_url}{href}")),
+        },
+    }
+}
+
+/// Make a rendered mirror page work as a file next to the other exported pages.
+fn rewrite_for_export(
+    html: &str,
+    account: &str,
+    repository: &str,
+    files: &PageFiles,
+    base_url: &str,
+) -> String {
+    let rewrite_href = |el: &mut Element| {
+        if let Some(href) = el
+            .get_attribute("href")
+            .and_then(|href| export_href(&href, account, repository, files, base_url))
+        {
+            el.set_attribute("href", &href).unwrap();
+        }
+    };
+
+    let mut output = Vec::new();
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![
+                element!("a[href], link[href]", |el: &mut Element| {
+                    rewrite_href(el);
+                    Ok(())
+                }),
+                // Proxied images are still fetched from the mirror.
+                element!("img[src^='/']:not([src^='//'])", |el: &mut Element| {
+                    let src = el.get_attribute("src").unwrap();
+                    el.set_attribute("src", &format!("{base_url}{src}"))
+                        .unwrap();
+                    Ok(())
+                }),
+                // There is nothing to search offline.
+                element!("form[role='search']", |el: &mut Element| {
+                    el.remove();
+                    Ok(())
+                }),
+            ],
+            ..Settings::default()
+        },
+        |c: &[u8]| output.extend_from_slice(c),
+    );
+
+    rewriter.write(html.as_bytes()).unwrap();
+    rewriter.end().unwrap();
+
+    String::from_utf8(output)
+        .unwrap()
+        .replace("url('/callToAction.svg')", "url('callToAction.svg')")
+}
+
+fn render_error(error: askama::Error) -> ContentError {
+    ContentError::OtherError(format!("Template render failed: {error}"))
+}
+
+/// A page of `pages` as a static file linking to the other exported files.
+pub fn render_page_file(
+    page: &WikiPageRef,
+    content: Content,
+    pages: &[WikiPageRef],
+    files: &PageFiles,
+    options: &RenderOptions,
+    base_url: &str,
+) -> Result<ExportFile, ContentError> {
+    let (account, repository) = (page.account.as_str(), page.repository.as_str());
+    // Listings percent-encode slugs, the rest of the mirror goes by page names.
+    let name = percent_decode_str(&page.slug).decode_utf8_lossy();
+    let (html, headings) =
+        add_heading_anchors(&content_to_html(content, account, repository, options));
+
+    let rendered = MirrorTemplate {
+        original_title: wiki_page_title(account, repository, &name),
+        original_url: github_wiki_url_encoded(account, repository, &name),
+        mirrored_content: html,
+        toc: render_toc(&headings),
+        index_url: mirror_index_url(account, repository),
+        search_url: mirror_search_url(account, repository),
+        meta: None,
+        breadcrumbs: wiki_breadcrumbs(
+            account,
+            repository,
+            wiki_page_name(account, repository, &name),
+        ),
+        navigation: page_navigation(pages, &page.slug),
+        snapshot_date: None,
+    }
+    .render()
+    .map_err(render_error)?;
+
+    Ok(ExportFile {
+        path: files.name(&page.slug),
+        contents: rewrite_for_export(&rendered, account, repository, files, base_url).into_bytes(),
+    })
+}
+
+/// The page index of `pages` as a static file.
+pub fn render_index_file(
+    account: &str,
+    repository: &str,
+    pages: &[WikiPageRef],
+    files: &PageFiles,
+    options: &RenderOptions,
+    base_url: &str,
+) -> Result<ExportFile, ContentError> {
+    let index = MirrorTemplate {
+        original_title: format!("Page Index - {account}/{repository} GitHub Wiki"),
+        original_url: github_wiki_url_encoded(account, repository, "Home"),
+        mirrored_content: content_to_html(wiki_index_content(pages), account, repository, options),
+        toc: None,
+        index_url: mirror_index_url(account, repository),
+        search_url: mirror_search_url(account, repository),
+        meta: None,
+        breadcrumbs: wiki_breadcrumbs(account, repository, "Page Index".to_string()),
+        navigation: None,
+        snapshot_date: None,
+    }
+    .render()
+    .map_err(render_error)?;
+
+    Ok(ExportFile {
+        path: INDEX_FILE.to_string(),
+        contents: rewrite_for_export(&index, account, repository, files, base_url).into_bytes(),
+    })
+}
+
+/// Page background the exported pages refer to.
+pub fn background_file() -> ExportFile {
+    ExportFile {
+        path: BACKGROUND_FILE.to_string(),
+        contents: include_bytes!("../templates/callToAction.svg").to_vec(),
+    }
+}
+
+/// Every page source of a wiki, fetched once for all export formats.
+pub struct WikiSources {
+    pub account: String,
+    pub repository: String,
+    pub pages: Vec<WikiPageRef>,
+    /// Source of each of `pages`, `None` when a page was listed but is gone by now.
+    pub contents: Vec<Option<Content>>,
+    pub sidebar: Option<Content>,
+}
+
+async fn retrieve_if_found(
+    account: &str,
+    repository: &str,
+    page: &str,
+    client: &Client,
+) -> Result<Option<Content>, ContentError> {
+    match retrieve_source_file(account, repository, page, client).await {
+        Ok(content) => Ok(Some(content)),
+        Err(ContentError::NotFound) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Fetch the listing, `_Sidebar` and every page of a wiki.
+pub async fn retrieve_wiki_sources(
+    account: &str,
+    repository: &str,
+    client: &Client,
+) -> Result<WikiSources, ContentError> {
+    let listing = retrieve_wiki_page_listing(account, repository, client).await?;
+    let sidebar = retrieve_if_found(account, repository, "_Sidebar", client).await?;
+
+    // Created up front: mapping inside the stream defeats the `Send` inference Rocket needs.
+    let fetches: Vec<_> = listing
+        .pages
+        .iter()
+        .map(|page| {
+            let name = percent_decode_str(&page.slug).decode_utf8_lossy();
+            async move { retrieve_if_found(account, repository, &name, client).await }
+        })
+        .collect();
+    let contents = stream::iter(fetches)
+        .buffered(CONCURRENT_PAGES)
+        .try_collect()
+        .await?;
+
+    Ok(WikiSources {
+        account: account.to_string(),
+        repository: repository.to_string(),
+        pages: listing.pages,
+        contents,
+        sidebar,
+    })
+}
+
+/// Render every page of a wiki, and its page index, as linked static HTML files.
+pub fn export_wiki(
+    sources: &WikiSources,
+    options: &RenderOptions,
+    base_url: &str,
+) -> Result<Vec<ExportFile>, ContentError> {
+    let (account, repository) = (sources.account.as_str(), sources.repository.as_str());
+    let pages = &sources.pages;
+    let page_files = PageFiles::new(pages);
+
+    let mut files = vec![
+        render_index_file(account, repository, pages, &page_files, options, base_url)?,
+        background_file(),
+    ];
+    for (page, content) in pages.iter().zip(&sources.contents) {
+        if let Some(content) = content {
+            files.push(render_page_file(
+                page,
+                content.clone(),
+                pages,
+                &page_files,
+                options,
+                base_url,
+            )?);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Write an export into `directory`, creating it if needed.
+pub fn write_directory(files: &[ExportFile], directory: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(directory)?;
+    for file in files {
+        std::fs::write(directory.join(&file.path), &file.contents)?;
+    }
+    Ok(())
+}
+
+/// Zip archive of an export, with every file inside the folder `root`.
+pub fn write_zip(files: &[ExportFile], root: &str) -> zip::result::ZipResult<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for file in files {
+        zip.start_file(format!("{root}/{}", file.path), options)?;
+        zip.write_all(&file.contents)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Single-file downloads of a whole wiki.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    /// Zip of the static HTML pages.
+    Zip,
+    /// All pages in one HTML document.
+    SinglePage,
+    Epub,
+}
+
+impl ExportFormat {
+    /// Format for an output file name's extension.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "zip" => Some(Self::Zip),
+            "html" => Some(Self::SinglePage),
+            "epub" => Some(Self::Epub),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::SinglePage => "html",
+            Self::Epub => "epub",
+        }
+    }
+
+    fn content_type(self) -> ContentType {
+        match self {
+            Self::Zip => ContentType::ZIP,
+            Self::SinglePage => ContentType::HTML,
+            Self::Epub => ContentType::new("application", "epub+zip"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error(transparent)]
+    Content(#[from] ContentError),
+    #[error(transparent)]
+    Book(#[from] BookError),
+    #[error("could not write zip: {0}")]
+    Zip(#[from] zip::result::ZipError),
+}
+
+/// A whole wiki as one file.
+pub fn export_file(
+    format: ExportFormat,
+    sources: &WikiSources,
+    options: &RenderOptions,
+    base_url: &str,
+) -> Result<Vec<u8>, ExportError> {
+    let (account, repository) = (sources.account.as_str(), sources.repository.as_str());
+    Ok(match format {
+        ExportFormat::Zip => {
+            let files = export_wiki(sources, options, base_url)?;
+            write_zip(&files, &format!("{account}-{repository}-wiki"))?
+        }
+        ExportFormat::SinglePage => {
+            let chapters = book_chapters(sources, options);
+            single_page_html(&chapters, account, repository, base_url)?.into_bytes()
+        }
+        ExportFormat::Epub => epub(
+            &book_chapters(sources, options),
+            account,
+            repository,
+            base_url,
+        )?,
+    })
+}
+
+#[derive(Responder)]
+pub struct ExportResponse {
+    file: (ContentType, Vec<u8>),
+    content_disposition: Header<'static>,
+}
+
+fn export_error(error: ExportError) -> status::Custom<String> {
+    let status = match error {
+        ExportError::Content(ContentError::NotFound) => Status::NotFound,
+        ExportError::Content(ContentError::TooMayRequests) => Status::TooManyRequests,
+        ExportError::Content(ContentError::Decommissioned) => Status::Gone,
+        _ => Status::InternalServerError,
+    };
+    status::Custom(status, format!("Error: {error}"))
+}
+
+/// A wiki's sources for a served export, fetched at most once an hour for all formats.
+async fn served_sources(
+    account: &str,
+    repository: &str,
+    client: &Client,
+) -> Result<Arc<WikiSources>, status::Custom<String>> {
+    let key = (account.to_string(), repository.to_string());
+    if let Some(sources) = SOURCES_CACHE.get(&key) {
+        return Ok(sources);
+    }
+
+    let Ok(_permit) = SERVED_EXPORTS.try_acquire() else {
+        return Err(status::Custom(
+            Status::ServiceUnavailable,
+            "Too many exports are being made right now. Try again later.".to_string(),
+        ));
+    };
+    let listing = retrieve_wiki_page_listing(account, repository, client)
+        .await
+        .map_err(|error| export_error(error.into()))?;
+    if listing.pages.len() > MAX_SERVED_EXPORT_PAGES {
+        return Err(status::Custom(
+            Status::PayloadTooLarge,
+            format!(
+                "This wiki has {} pages, too many to export here. Use the `export` command instead.",
+                listing.pages.len()
+            ),
+        ));
+    }
+
+    let sources = Arc::new(
+        retrieve_wiki_sources(account, repository, client)
+            .await
+            .map_err(|error| export_error(error.into()))?,
+    );
+    SOURCES_CACHE.insert(key, sources.clone());
+    Ok(sources)
+}
+
+async fn served_export(
+    format: ExportFormat,
+    account: &str,
+    repository: &str,
+    client: &Client,
+    config: &Config,
+) -> Result<ExportResponse, status::Custom<String>> {
+    if !config.serve_exports {
+        return Err(status::Custom(
+            Status::NotFound,
+            "Exports are not served here. Use the `export` command instead.".to_string(),
+        ));
+    }
+
+    let key = (account.to_string(), repository.to_string(), format);
+    let file = match EXPORT_CACHE.get(&key) {
+        Some(file) => file,
+        None => {
+            let sources = served_sources(account, repository, client).await?;
+            let options = config.render_options();
+            let base_url = config.base_url().to_string();
+            let file = tokio::task::spawn_blocking(move || {
+                export_file(format, &sources, &options, &base_url)
+            })
+            .await
+            .map_err(|error| {
+                status::Custom(Status::InternalServerError, format!("Error: {error}"))
+            })?
+            .map_err(export_error)?;
+            EXPORT_CACHE.insert(key, file.clone());
+            file
+        }
+    };
+
+    Ok(ExportResponse {
+        file: (format.content_type(), file),
+        content_disposition: Header::new(
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"{account}-{repository}-wiki.{}\"",
+                format.extension()
+            ),
+        ),
+    })
+}
+
+#[get("/<account>/<repository>/export.zip")]
+async fn export_zip(
+    account: &str,
+    repository: &str,
+    client: &State<Client>,
+    config: &State<Config>,
+) -> Result<ExportResponse, status::Custom<String>> {
+    served_export(ExportFormat::Zip, account, repository, client, config).await
+}
+
+#[get("/<account>/<repository>/book.html")]
+async fn export_single_page(
+    account: &str,
+    repository: &str,
+    client: &State<Client>,
+    config: &State<Config>,
+) -> Result<ExportResponse, status::Custom<String>> {
+    served_export(
+        ExportFormat::SinglePage,
+        account,
+        repository,
+        client,
+        config,
+    )
+    .await
+}
+
+#[get("/<account>/<repository>/book.epub")]
+async fn export_epub(
+    account: &str,
+    repository: &str,
+    client: &State<Client>,
+    config: &State<Config>,
+) -> Result<ExportResponse, status::Custom<String>> {
+    served_export(ExportFormat::Epub, account, repository, client, config).await
+}
+
+pub fn export_routes() -> Vec<Route> {
+    routes![export_zip, export_single_page, export_epub]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_hrefs() {
+        let files = PageFiles::new(&[]);
+        let href = |href| export_href(href, "a", "r", &files, "https://example.com");
+
+        assert_eq!(href("/m/a/r/wiki").as_deref(), Some("Home.html"));
+        assert_eq!(
+            href("/m/a/r/wiki#usage").as_deref(),
+            Some("Home.html#usage")
+        );
+        assert_eq!(
+            href("/m/a/r/wiki/Getting-Started#install").as_deref(),
+            Some("Getting-Started.html#install")
+        );
+        assert_eq!(
+            href("/m/a/r/wiki/Caf%C3%A9").as_deref(),
+            Some("Caf%C3%A9.html")
+        );
+        assert_eq!(href("/m/a/r/wiki_index").as_deref(), Some("_index.html"));
+        assert_eq!(
+            href("/m/b/r/wiki/Other").as_deref(),
+            Some("https://example.com/m/b/r/wiki/Other")
+        );
+        assert_eq!(
+            href("/img?url=x").as_deref(),
+            Some("https://example.com/img?url=x")
+        );
+        assert_eq!(href("https://github.com/a/r"), None);
+        assert_eq!(href("//example.org/x"), None);
+        assert_eq!(href("#usage"), None);
+
+        assert_eq!(files.name("Caf%C3%A9"), "Café.html");
+        assert_eq!(files.name("..%2F..%2Fetc"), "..-..-etc.html");
+    }
+
+    fn page(slug: &str) -> WikiPageRef {
+        WikiPageRef {
+            account: "a".to_string(),
+            repository: "r".to_string(),
+            slug: slug.to_string(),
+            title: slug.to_string(),
+        }
+    }
+
+    #[test]
+    fn page_file_names_stay_unique() {
+        let files = PageFiles::new(&[
+            page("index"),
+            page("_index"),
+            page("Setup"),
+            page("setup"),
+            page("Setup-2"),
+            page("How-to%3F"),
+            page("How-to%2A"),
+            page("a%3Cb%3E%7Cc%22d"),
+            page("Version%201.0."),
+            page("Trailing%20"),
+            page("con"),
+            page("Com3.txt"),
+            page("Console"),
+        ]);
+
+        assert_eq!(files.name("index"), "index.html");
+        assert_eq!(files.name("_index"), "_index-2.html");
+        assert_eq!(files.name("Setup"), "Setup.html");
+        assert_eq!(files.name("setup"), "setup-3.html");
+        assert_eq!(files.name("Setup-2"), "Setup-2.html");
+        assert_eq!(files.href("setup"), "setup-3.html");
+        assert_eq!(files.name("How-to%3F"), "How-to-.html");
+        assert_eq!(files.name("How-to*"), "How-to--2.html");
+        assert_eq!(files.name("a<b>|c\"d"), "a-b--c-d.html");
+        assert_eq!(files.name("Version 1.0."), "Version 1.0-.html");
+        assert_eq!(files.name("Trailing "), "Trailing-.html");
+        assert_eq!(files.name("con"), "_con.html");
+        assert_eq!(files.name("Com3.txt"), "_Com3.txt.html");
+        assert_eq!(files.name("Console"), "Console.html");
+    }
+
+    #[test]
+    fn index_page_keeps_the_page_index() {
+        let pages = [page("Home"), page("index")];
+        let files = PageFiles::new(&pages);
+        let options = RenderOptions::default();
+        let base_url = "https://example.com";
+
+        let mut export = vec![
+            render_index_file("a", "r", &pages, &files, &options, base_url).unwrap(),
+            background_file(),
+        ];
+        for page in &pages {
+            let content = Content::Markdown(format!("# {}", page.slug));
+            export
+                .push(render_page_file(page, content, &pages, &files, &options, base_url).unwrap());
+        }
+
+        let paths: Vec<&str> = export.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [INDEX_FILE, BACKGROUND_FILE, "Home.html", "index.html"]
+        );
+        let zip = write_zip(&export, "a-r-wiki").unwrap();
+        assert_eq!(zip::ZipArchive::new(Cursor::new(zip)).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn exported_pages_link_to_files() {
+        let html = rewrite_for_export(
+            "<style>body { background-image: url('/callToAction.svg'); }</style><a href=\"/m/a/r/wiki/Setup\">Setup</a><a href=\"/m/a/r/wiki/Setup.md\">Source</a><a href=\"/m/a/r/wiki/Notes.md\">Notes</a><img src=\"/img?url=x\"><form action=\"/m/a/r/search\" role=\"search\"><input name=\"q\"></form>",
+            "a",
+            "r",
+            &PageFiles::new(&[page("Notes.md")]),
+            "https://example.com",
+        );
+
+        assert_eq!(
+            html,
+            "<style>body { background-image: url('callToAction.svg'); }</style><a href=\"Setup.html\">Setup</a><a href=\"https://example.com/m/a/r/wiki/Setup.md\">Source</a><a href=\"Notes.md.html\">Notes</a><img src=\"https://example.com/img?url=x\">"
+        );
+    }
+
+    #[test]
+    fn zips_under_root() {
+        let zip = write_zip(
+            &[ExportFile {
+                path: "Home.html".to_string(),
+                contents: b"<p>Home</p>".to_vec(),
+            }],
+            "a-r-wiki",
+        )
+        .unwrap();
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(zip)).unwrap();
+        assert_eq!(archive.len(), 1);
+        assert!(archive.by_name("a-r-wiki/Home.html").is_ok());
+    }
+}
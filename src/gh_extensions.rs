@@ -1,50 +1,346 @@
-use regex::{Captures, Regex};
+use regex::Regex;
 use std::sync::LazyLock;
 
-static IMAGE_LINK_RE: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new("\\[\\[(?P<image_url>.*\\.(?i)(jpg|jpeg|png|gif))\\|(alt=)?(?P<link_text>.*?)\\]\\]")
-        .expect("image regex should compile")
-});
-static REPO_BLOB_RE: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"(?P<pre>!\[.*\]\(https://github.com/.+/.+)/blob/(?P<suf>.*)")
+use crate::links::github_blob_to_raw;
+use crate::scraper::escape_html;
+
+static REPO_BLOB_IMAGE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?P<pre>!\[[^\]\n]*\]\(\s*https://github\.com/[^/\s)]+/[^/\s)]+)/blob/")
         .expect("blob regex should compile")
 });
-static WIKI_LINK_RE: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new("\\[\\[((?P<link_text>.*?)\\| *)?(?P<page_name>.*?)\\]\\]")
-        .expect("wiki link regex should compile")
-});
+
+const IMAGE_EXTENSIONS: &[&str] = &[".jpg", ".jpeg", ".png", ".gif", ".svg", ".webp"];
+
+/// An image embedded with `[[image.png|alt=Text|width=100px|align=left|link=Page]]`.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct WikiImage<'a> {
+    url: &'a str,
+    alt: Option<&'a str>,
+    width: Option<&'a str>,
+    height: Option<&'a str>,
+    align: Option<&'a str>,
+    link: Option<&'a str>,
+}
+
+// Only sizes like `100`, `100px` or `50%`, and the classic `align` keywords.
+fn image_attribute_value<'a>(name: &str, value: &'a str) -> Option<&'a str> {
+    let allowed = match name {
+        "width" | "height" => {
+            let number = value
+                .strip_suffix("px")
+                .or_else(|| value.strip_suffix('%'))
+                .unwrap_or(value);
+            !number.is_empty() && number.chars().all(|c| c.is_ascii_digit() || c == '.')
+        }
+        "align" => matches!(
+            value,
+            "left" | "right" | "center" | "top" | "middle" | "bottom"
+        ),
+        _ => false,
+    };
+    allowed.then_some(value)
+}
+
+impl<'a> WikiImage<'a> {
+    fn parse(inner: &'a str) -> Option<Self> {
+        let mut parts = inner.split('|').map(str::trim);
+        let url = parts.next()?;
+        let lower_url = url.to_ascii_lowercase();
+        if !IMAGE_EXTENSIONS
+            .iter()
+            .any(|extension| lower_url.ends_with(extension))
+        {
+            return None;
+        }
+
+        let mut image = WikiImage {
+            url,
+            ..WikiImage::default()
+        };
+        for part in parts {
+            match part.split_once('=') {
+                Some(("alt", value)) => image.alt = Some(value),
+                Some(("link", value)) => image.link = Some(value),
+                Some((name @ "width", value)) => image.width = image_attribute_value(name, value),
+                Some((name @ "height", value)) => image.height = image_attribute_value(name, value),
+                Some((name @ "align", value)) => image.align = image_attribute_value(name, value),
+                // Gollum's `frame` and `float` flags have no GitHub equivalent
+                Some(_) | None if matches!(part, "frame" | "float") => {}
+                // Bare text is the alt text, like `[[image.png|A picture]]`
+                Some(_) | None => image.alt = Some(part),
+            }
+        }
+        Some(image)
+    }
+
+    fn to_markdown(&self, account: &str, repo: &str) -> String {
+        let url = if is_absolute_url(self.url) {
+            github_blob_to_raw(self.url).unwrap_or_else(|| self.url.to_string())
+        } else {
+            format!(
+                "https://raw.githubusercontent.com/wiki/{account}/{repo}/{}",
+                self.url.trim_start_matches('/')
+            )
+        };
+        let attributes: String = [
+            ("width", self.width),
+            ("height", self.height),
+            ("align", self.align),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|value| format!(" {name}=\"{value}\"")))
+        .collect();
+        let link = self.link.map(|link| wiki_link_target(link, account, repo));
+
+        // Markdown has no syntax for sizes, so those images are written as HTML.
+        if attributes.is_empty() {
+            let image = format!(
+                "![{}]({})",
+                markdown_text(self.alt.unwrap_or_default()),
+                markdown_destination(&url)
+            );
+            return match link {
+                Some(link) => format!("[{image}]({})", markdown_destination(&link)),
+                None => image,
+            };
+        }
+        let image = format!(
+            "<img src=\"{}\" alt=\"{}\"{attributes}>",
+            escape_html(&url),
+            escape_html(self.alt.unwrap_or_default())
+        );
+        match link {
+            Some(link) => format!("<a href=\"{}\">{image}</a>", escape_html(&link)),
+            // A tag alone on a line would start an HTML block, swallowing the lines after it.
+            None => format!("<span>{image}</span>"),
+        }
+    }
+}
+
+/// Text that stays literal inside Markdown link brackets.
+fn markdown_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A Markdown link destination, in `<...>` when it holds spaces, parentheses and the like.
+fn markdown_destination(url: &str) -> String {
+    if !url.contains(|c: char| c.is_whitespace() || c.is_control() || "()<>\\".contains(c)) {
+        return url.to_string();
+    }
+    let mut escaped = String::with_capacity(url.len() + 2);
+    escaped.push('<');
+    for c in url.chars() {
+        if matches!(c, '\\' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped.push('>');
+    escaped
+}
+
+fn is_absolute_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+// `Page Name#Some Section` to `/account/repo/wiki/Page-Name#some-section`
+fn wiki_link_target(page: &str, account: &str, repo: &str) -> String {
+    if is_absolute_url(page) {
+        return page.to_string();
+    }
+    let (page_name, anchor) = match page.split_once('#') {
+        Some((page_name, anchor)) => (page_name.trim(), Some(anchor.trim())),
+        None => (page, None),
+    };
+    let anchor = anchor
+        .map(|anchor| format!("#{}", anchor.replace(' ', "-").to_lowercase()))
+        .unwrap_or_default();
+    if page_name.is_empty() {
+        return anchor;
+    }
+
+    let page_name_link = page_name.replace(' ', "-");
+    format!("/{account}/{repo}/wiki/{page_name_link}{anchor}")
+}
+
+// `[[Page]]`, `[[Link text|Page]]`, `[[Page#anchor]]` and `[[image.png|alt=Text]]`
+fn wiki_link_to_markdown(inner: &str, account: &str, repo: &str) -> String {
+    if let Some(image) = WikiImage::parse(inner) {
+        return image.to_markdown(account, repo);
+    }
+
+    let (link_text, page_name) = match inner.split_once('|') {
+        Some((link_text, page_name)) => (link_text, page_name.trim()),
+        None => (inner, inner.trim()),
+    };
+    format!(
+        "[{link_text}]({})",
+        wiki_link_target(page_name, account, repo)
+    )
+}
+
+// Fenced code block opener/closer: up to three spaces, then three or more ` or ~.
+fn code_fence(line: &str) -> Option<(char, usize)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let fence_char = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let fence_len = rest.len() - rest.trim_start_matches(fence_char).len();
+    (fence_len >= 3).then_some((fence_char, fence_len))
+}
+
+// Line indented enough to be part of an indented code block.
+fn is_indented(line: &str) -> bool {
+    line.starts_with("    ") || line.trim_start_matches(' ').starts_with('\t')
+}
+
+// `- item`, `* item`, `+ item`, `1. item` or `1) item`, whose indented lines are not code.
+fn is_list_item(line: &str) -> bool {
+    let line = line.trim_start();
+    let marker = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    let marker = if marker.len() < line.len() {
+        marker.strip_prefix(['.', ')'])
+    } else {
+        marker.strip_prefix(['-', '*', '+'])
+    };
+    marker.is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t', '\n']))
+}
+
+// Offset of the first blank line after the first line of `text`, where a paragraph ends.
+fn paragraph_end(text: &str) -> usize {
+    let mut offset = 0;
+    for (number, line) in text.split_inclusive('\n').enumerate() {
+        if number > 0 && line.trim().is_empty() {
+            return offset;
+        }
+        offset += line.len();
+    }
+    text.len()
+}
+
+// Length of the code span at the start of `text`, which closes on a backtick run
+// of exactly the same length as the one opening it, within the same paragraph.
+fn code_span_len(text: &str) -> Option<usize> {
+    let text = &text[..paragraph_end(text)];
+    let run = text.len() - text.trim_start_matches('`').len();
+    let mut search = run;
+    while let Some(found) = text[search..].find('`') {
+        let start = search + found;
+        let close_run = text[start..].len() - text[start..].trim_start_matches('`').len();
+        if close_run == run {
+            return Some(start + close_run);
+        }
+        search = start + close_run;
+    }
+    None
+}
+
+fn push_plain(plain: &str, output: &mut String) {
+    output.push_str(&REPO_BLOB_IMAGE_RE.replace_all(plain, "$pre/raw/"));
+}
+
+// Rewrite wiki links in text that is not inside a fenced code block.
+// Inline code spans and backslash escapes are passed through untouched.
+fn convert_inline(text: &str, account: &str, repo: &str, output: &mut String) {
+    let mut plain_start = 0;
+    let mut position = 0;
+
+    while position < text.len() {
+        let rest = &text[position..];
+        if rest.starts_with('\\') {
+            position += rest.chars().take(2).map(char::len_utf8).sum::<usize>();
+        } else if rest.starts_with('`') {
+            match code_span_len(rest) {
+                Some(len) => {
+                    push_plain(&text[plain_start..position], output);
+                    output.push_str(&rest[..len]);
+                    position += len;
+                    plain_start = position;
+                }
+                None => position += rest.len() - rest.trim_start_matches('`').len(),
+            }
+        } else if rest.starts_with("[[") {
+            let link_end = rest.find("]]").filter(|end| !rest[..*end].contains('\n'));
+            match link_end {
+                Some(end) => {
+                    push_plain(&text[plain_start..position], output);
+                    output.push_str(&wiki_link_to_markdown(&rest[2..end], account, repo));
+                    position += end + 2;
+                    plain_start = position;
+                }
+                None => position += 2,
+            }
+        } else {
+            position += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    push_plain(&text[plain_start..], output);
+}
 
 // Apparently the wiki part of GitHub can also take mediawiki syntax!
 // https://docs.github.com/en/communities/documenting-your-project-with-wikis/editing-wiki-content
-// Transform them to pure markdown
-// Transform images first, then links
+// Transform them to pure markdown, leaving code blocks and code spans alone.
 pub fn github_wiki_markdown_to_pure_markdown(md: &str, account: &str, repo: &str) -> String {
-    // Disregard alt for now.
-    let processed_img_md = IMAGE_LINK_RE.replace_all(
-        md,
-        format!("![$link_text](https://raw.githubusercontent.com/wiki/{account}/{repo}$image_url)"),
-    );
-
-    let processed_blob_md = REPO_BLOB_RE.replace_all(&processed_img_md, "$pre/raw/$suf");
-
-    WIKI_LINK_RE
-        .replace_all(&processed_blob_md, |caps: &Captures<'_>| {
-            let page_name = caps
-                .name("page_name")
-                .map_or("", |page_name| page_name.as_str());
-            let link_text = caps
-                .name("link_text")
-                .map_or(page_name, |link_text| link_text.as_str());
-
-            if page_name.starts_with("http://") || page_name.starts_with("https://") {
-                return format!("[{link_text}]({page_name})");
+    let mut output = String::with_capacity(md.len());
+    let mut open_fence: Option<(char, usize)> = None;
+    let mut text = String::new();
+    // Indented code needs a blank line before it, and is not a list item's continuation.
+    let mut after_blank = true;
+    let mut in_list = false;
+    let mut indented_code = false;
+
+    for line in md.split_inclusive('\n') {
+        let blank = line.trim().is_empty();
+        if open_fence.is_none() {
+            indented_code = if indented_code {
+                blank || is_indented(line)
+            } else {
+                after_blank && !in_list && !blank && is_indented(line)
+            };
+            if !blank && !indented_code {
+                in_list = is_list_item(line) || (in_list && (is_indented(line) || !after_blank));
+            }
+            after_blank = blank;
+            if indented_code {
+                convert_inline(&text, account, repo, &mut output);
+                text.clear();
+                output.push_str(line);
+                continue;
             }
+        }
 
-            let page_name_link = page_name.replace(' ', "-");
+        match (open_fence, code_fence(line)) {
+            (Some((open_char, open_len)), Some((fence_char, fence_len)))
+                if fence_char == open_char
+                    && fence_len >= open_len
+                    && line.trim().trim_start_matches(fence_char).is_empty() =>
+            {
+                open_fence = None;
+                output.push_str(line);
+            }
+            (Some(_), _) => output.push_str(line),
+            (None, Some(fence)) => {
+                convert_inline(&text, account, repo, &mut output);
+                text.clear();
+                open_fence = Some(fence);
+                output.push_str(line);
+            }
+            (None, None) => text.push_str(line),
+        }
+    }
+    convert_inline(&text, account, repo, &mut output);
 
-            format!("[{link_text}](/{account}/{repo}/wiki/{page_name_link})")
-        })
-        .to_string()
+    output
 }
 
 #[cfg(test)]
@@ -93,6 +389,74 @@ mod tests {
         assert_eq!(result, "[10, The TV Series](http://www.10-la-serie.ch/)");
     }
 
+    #[test]
+    fn anchor_links() {
+        let md = "[[Install#Build From Source]] and [[see below|#Known Issues]]";
+        let result = github_wiki_markdown_to_pure_markdown(md, "a", "r");
+        assert_eq!(
+            result,
+            "[Install#Build From Source](/a/r/wiki/Install#build-from-source) and [see below](#known-issues)"
+        );
+    }
+
+    #[test]
+    fn image_attributes() {
+        let md = "[[images/logo.png|alt=Logo|width=100px|height=50px|align=left|link=Home]]";
+        let result = github_wiki_markdown_to_pure_markdown(md, "a", "r");
+        assert_eq!(
+            result,
+            "<a href=\"/a/r/wiki/Home\"><img src=\"https://raw.githubusercontent.com/wiki/a/r/images/logo.png\" alt=\"Logo\" width=\"100px\" height=\"50px\" align=\"left\"></a>"
+        );
+    }
+
+    #[test]
+    fn image_attributes_are_checked() {
+        let md = "[[x.png|alt=\"X\"|width=1px\"onerror=alert(1)|height=50%|align=javascript]]";
+        let result = github_wiki_markdown_to_pure_markdown(md, "a", "r");
+        assert_eq!(
+            result,
+            "<span><img src=\"https://raw.githubusercontent.com/wiki/a/r/x.png\" alt=\"&quot;X&quot;\" height=\"50%\"></span>"
+        );
+    }
+
+    #[test]
+    fn several_images_on_one_line() {
+        let md = "[[a.png|alt=A]] [[b.gif|alt=B]]";
+        let result = github_wiki_markdown_to_pure_markdown(md, "a", "r");
+        assert_eq!(
+            result,
+            "![A](https://raw.githubusercontent.com/wiki/a/r/a.png) ![B](https://raw.githubusercontent.com/wiki/a/r/b.gif)"
+        );
+    }
+
+    #[test]
+    fn image_alt_and_path_are_escaped() {
+        let md =
+            "[[images/my diagram (v2).png|alt=a [draft] \\ note]] after [[x.png|link=Other Page]]";
+        let result = github_wiki_markdown_to_pure_markdown(md, "a", "r");
+        assert_eq!(
+            result,
+            "![a \\[draft\\] \\\\ note](<https://raw.githubusercontent.com/wiki/a/r/images/my diagram (v2).png>) after [![](https://raw.githubusercontent.com/wiki/a/r/x.png)](/a/r/wiki/Other-Page)"
+        );
+    }
+
+    #[test]
+    fn code_is_left_alone() {
+        let md = "`[[Page]]` ``a ` [[Page]]`` [[Page]]\n```\n[[Page]]\n```\n~~~~\n[[Page]]\n~~~\n~~~~\n[[Page]]";
+        let result = github_wiki_markdown_to_pure_markdown(md, "a", "r");
+        assert_eq!(
+            result,
+            "`[[Page]]` ``a ` [[Page]]`` [Page](/a/r/wiki/Page)\n```\n[[Page]]\n```\n~~~~\n[[Page]]\n~~~\n~~~~\n[Page](/a/r/wiki/Page)"
+        );
+    }
+
+    #[test]
+    fn stray_backtick_ends_with_paragraph() {
+        let md = "A ` alone\n\n[[Page]] and `[[Code]]`";
+        let result = github_wiki_markdown_to_pure_markdown(md, "a", "r");
+        assert_eq!(result, "A ` alone\n\n[Page](/a/r/wiki/Page) and `[[Code]]`");
+    }
+
     #[test]
     fn sidebar_links() {
         let md = include_str!("../test-data/_Sidebar.md");
@@ -103,4 +467,11 @@ mod tests {
         );
         assert_eq!(result, include_str!("../test-data/_Sidebar_pure.md"));
     }
+
+    #[test]
+    fn wiki_link_fixtures() {
+        let md = include_str!("../test-data/wiki-links.md");
+        let result = github_wiki_markdown_to_pure_markdown(md, "octo", "wiki-links");
+        assert_eq!(result, include_str!("../test-data/wiki-links_pure.md"));
+    }
 }
@@ -0,0 +1,100 @@
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use syntect::html::{ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use crate::scraper::escape_html;
+
+/// Prefix of the classes on highlighted spans, matching `templates/highlight.css`.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// CPU time one page may spend on highlighting; later code blocks stay plain.
+const PAGE_BUDGET: Duration = Duration::from_millis(250);
+// Huge blocks are usually generated data, not worth the time.
+const MAX_BLOCK_BYTES: usize = 64 * 1024;
+// The budget is only checked between lines, and one long line can take arbitrarily long.
+const MAX_LINE_BYTES: usize = 4 * 1024;
+
+// Loading the grammars takes a while, so only do it once a page needs them.
+static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+
+/// Highlights the code blocks of one page until its time budget runs out.
+pub struct Highlighter {
+    deadline: Instant,
+}
+
+impl Highlighter {
+    pub fn new() -> Self {
+        Self {
+            deadline: Instant::now() + PAGE_BUDGET,
+        }
+    }
+
+    /// `<pre>` with class-annotated spans for `code`, or `None` to render it plainly.
+    pub fn highlight(&self, code: &str, language: &str) -> Option<String> {
+        if language.is_empty()
+            || code.len() > MAX_BLOCK_BYTES
+            || code.lines().any(|line| line.len() > MAX_LINE_BYTES)
+            || Instant::now() >= self.deadline
+        {
+            return None;
+        }
+        let syntax = SYNTAX_SET.find_syntax_by_token(language)?;
+
+        let mut generator =
+            ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAX_SET, CLASS_STYLE);
+        for line in LinesWithEndings::from(code) {
+            if Instant::now() >= self.deadline {
+                return None;
+            }
+            generator
+                .parse_html_for_line_which_includes_newline(line)
+                .ok()?;
+        }
+
+        Some(format!(
+            "<pre lang=\"{}\"><code>{}</code></pre>\n",
+            escape_html(language),
+            generator.finalize()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_known_languages() {
+        let highlighter = Highlighter::new();
+
+        let html = highlighter
+            .highlight("fn main() {}\n", "rust")
+            .expect("rust should highlight");
+        assert!(html.starts_with("<pre lang=\"rust\"><code><span class=\"hl-source hl-rust\">"));
+        assert!(html.contains("<span class=\"hl-storage hl-type hl-function hl-rust\">fn</span>"));
+        assert!(html.ends_with("</code></pre>\n"));
+
+        assert_eq!(highlighter.highlight("x", "not-a-language"), None);
+        assert_eq!(highlighter.highlight("x", ""), None);
+    }
+
+    #[test]
+    fn skips_long_lines() {
+        let highlighter = Highlighter::new();
+        let code = format!("let x = \"{}\";\n", "a".repeat(MAX_LINE_BYTES));
+
+        assert_eq!(highlighter.highlight(&code, "rust"), None);
+    }
+
+    #[test]
+    fn stops_after_budget() {
+        let highlighter = Highlighter {
+            deadline: Instant::now(),
+        };
+
+        assert_eq!(highlighter.highlight("fn main() {}\n", "rust"), None);
+    }
+}
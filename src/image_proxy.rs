@@ -0,0 +1,217 @@
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use reqwest::header::LOCATION;
+use reqwest::{Client, StatusCode, Url};
+use rocket::http::{ContentType, Header, Status};
+use rocket::{get, routes, Responder, Route, State};
+
+use crate::cache::TtlCache;
+use crate::config::Config;
+
+/// Hosts GitHub serves wiki and README images from. Nothing else is fetched.
+const ALLOWED_HOSTS: &[&str] = &[
+    "github.com",
+    "raw.githubusercontent.com",
+    "user-images.githubusercontent.com",
+    "private-user-images.githubusercontent.com",
+    "camo.githubusercontent.com",
+    "avatars.githubusercontent.com",
+    "objects.githubusercontent.com",
+];
+
+const MAX_IMAGE_BYTES: usize = 4 * 1024 * 1024;
+const MAX_REDIRECTS: usize = 5;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Bounded by bytes, since images take up to `MAX_IMAGE_BYTES` each.
+const IMAGE_CACHE_BYTES: usize = 32 * 1024 * 1024;
+
+static IMAGE_CACHE: LazyLock<TtlCache<String, (ContentType, Vec<u8>)>> = LazyLock::new(|| {
+    TtlCache::with_weigher(
+        Duration::from_secs(60 * 60),
+        IMAGE_CACHE_BYTES,
+        |(_, bytes)| bytes.len(),
+    )
+});
+
+fn allowed_url(url: &str) -> Option<Url> {
+    let url = Url::parse(url).ok()?;
+    let allowed = url.scheme() == "https"
+        && url.port().is_none()
+        && url
+            .host_str()
+            .is_some_and(|host| ALLOWED_HOSTS.contains(&host));
+    allowed.then_some(url)
+}
+
+/// Mirror-local URL serving `src` through the proxy, or `None` if the host is not allowed.
+pub fn proxied_image_src(src: &str) -> Option<String> {
+    allowed_url(src)?;
+    Some(format!(
+        "/img?url={}",
+        utf8_percent_encode(src, NON_ALPHANUMERIC)
+    ))
+}
+
+/// Decide what an image is from its bytes; upstream `Content-Type` headers are not trusted.
+fn sniff_image_type(bytes: &[u8]) -> Option<ContentType> {
+    let content_type = match bytes {
+        [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => ContentType::PNG,
+        [0xff, 0xd8, 0xff, ..] => ContentType::JPEG,
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => ContentType::GIF,
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => ContentType::WEBP,
+        [_, _, _, _, b'f', b't', b'y', b'p', b'a', b'v', b'i', b'f', ..] => ContentType::AVIF,
+        [b'B', b'M', ..] => ContentType::BMP,
+        [0, 0, 1, 0, ..] => ContentType::Icon,
+        _ => {
+            let text = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]);
+            let text = text.trim_start_matches('\u{feff}').trim_start();
+            if (text.starts_with("<?xml") || text.starts_with("<svg")) && text.contains("<svg") {
+                ContentType::SVG
+            } else {
+                return None;
+            }
+        }
+    };
+    Some(content_type)
+}
+
+async fn fetch_image(url: Url, client: &Client) -> Result<(ContentType, Vec<u8>), Status> {
+    let mut url = url;
+    for _ in 0..=MAX_REDIRECTS {
+        let mut response = client
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|_| Status::BadGateway)?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| url.join(location).ok())
+                .ok_or(Status::BadGateway)?;
+            url = allowed_url(location.as_str()).ok_or(Status::Forbidden)?;
+            continue;
+        }
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => return Err(Status::NotFound),
+            _ => return Err(Status::BadGateway),
+        }
+        if response
+            .content_length()
+            .is_some_and(|length| length > MAX_IMAGE_BYTES as u64)
+        {
+            return Err(Status::PayloadTooLarge);
+        }
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|_| Status::BadGateway)? {
+            if bytes.len() + chunk.len() > MAX_IMAGE_BYTES {
+                return Err(Status::PayloadTooLarge);
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        let content_type = sniff_image_type(&bytes).ok_or(Status::UnsupportedMediaType)?;
+        return Ok((content_type, bytes));
+    }
+    Err(Status::BadGateway)
+}
+
+#[derive(Responder)]
+pub struct ImageResponse {
+    image: (ContentType, Vec<u8>),
+    cache_control: Header<'static>,
+    // Proxied SVGs are served from our origin; never let them run script.
+    content_security_policy: Header<'static>,
+}
+
+#[get("/img?<url>")]
+async fn image_proxy(
+    url: &str,
+    client: &State<Client>,
+    config: &State<Config>,
+) -> Result<ImageResponse, Status> {
+    if !config.image_proxy {
+        return Err(Status::NotFound);
+    }
+    let upstream = allowed_url(url).ok_or(Status::Forbidden)?;
+
+    let image = match IMAGE_CACHE.get(&url.to_string()) {
+        Some(image) => image,
+        None => {
+            let image = tokio::time::timeout(FETCH_TIMEOUT, fetch_image(upstream, client))
+                .await
+                .map_err(|_| Status::GatewayTimeout)??;
+            IMAGE_CACHE.insert(url.to_string(), image.clone());
+            image
+        }
+    };
+
+    Ok(ImageResponse {
+        image,
+        cache_control: Header::new("Cache-Control", "public, max-age=86400"),
+        content_security_policy: Header::new(
+            "Content-Security-Policy",
+            "default-src 'none'; style-src 'unsafe-inline'; sandbox",
+        ),
+    })
+}
+
+pub fn image_proxy_routes() -> Vec<Route> {
+    routes![image_proxy]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowlisted_hosts_only() {
+        assert_eq!(
+            proxied_image_src("https://raw.githubusercontent.com/wiki/a/r/images/a b.png")
+                .as_deref(),
+            Some("/img?url=https%3A%2F%2Fraw%2Egithubusercontent%2Ecom%2Fwiki%2Fa%2Fr%2Fimages%2Fa%20b%2Epng")
+        );
+        for src in [
+            "http://raw.githubusercontent.com/a.png",
+            "https://raw.githubusercontent.com:8443/a.png",
+            "https://example.com/a.png",
+            "https://github.com.example.com/a.png",
+            "/a/r/wiki/a.png",
+            "data:image/png;base64,AAAA",
+        ] {
+            assert_eq!(proxied_image_src(src), None, "{src}");
+        }
+    }
+
+    #[test]
+    fn sniffs_image_types() {
+        assert_eq!(
+            sniff_image_type(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Some(ContentType::PNG)
+        );
+        assert_eq!(
+            sniff_image_type(b"\xff\xd8\xff\xe0\0\x10JFIF"),
+            Some(ContentType::JPEG)
+        );
+        assert_eq!(sniff_image_type(b"GIF89a\x01\0"), Some(ContentType::GIF));
+        assert_eq!(
+            sniff_image_type(b"RIFF\x24\0\0\0WEBPVP8 "),
+            Some(ContentType::WEBP)
+        );
+        assert_eq!(
+            sniff_image_type(
+                b"\xef\xbb\xbf<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>"
+            ),
+            Some(ContentType::SVG)
+        );
+        assert_eq!(sniff_image_type(b"<!DOCTYPE html><html>"), None);
+        assert_eq!(sniff_image_type(b"<?xml version=\"1.0\"?><feed/>"), None);
+        assert_eq!(sniff_image_type(b""), None);
+    }
+}
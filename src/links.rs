@@ -0,0 +1,381 @@
+const GITHUB: &str = "https://github.com";
+const RAW_WIKI_HOST: &str = "https://raw.githubusercontent.com/wiki";
+
+// Files people commit to wikis and link to, as opposed to pages.
+const ATTACHMENT_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "svg", "webp", "bmp", "ico", "pdf", "zip", "gz", "tgz", "tar",
+    "7z", "rar", "xz", "bz2", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "odt", "ods", "odp",
+    "csv", "txt", "json", "xml", "yml", "yaml", "mp3", "mp4", "wav", "webm", "mov", "exe", "msi",
+    "dmg", "deb", "rpm", "apk", "jar", "iso", "bin",
+];
+
+fn is_absolute(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://") || url.starts_with("//")
+}
+
+fn has_attachment_extension(path: &str) -> bool {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let file_name = path.rsplit('/').next().unwrap_or_default();
+    file_name.rsplit_once('.').is_some_and(|(name, extension)| {
+        !name.is_empty()
+            && ATTACHMENT_EXTENSIONS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(extension))
+    })
+}
+
+/// URL of a file in the wiki's own git repository.
+pub fn raw_wiki_file_url(account: &str, repository: &str, path: &str) -> String {
+    format!(
+        "{RAW_WIKI_HOST}/{account}/{repository}/{}",
+        path.trim_start_matches('/')
+    )
+}
+
+// Resolve a root-relative GitHub path (`/owner/repo/...`) to a file URL, if it names
+// a file in a wiki (`/owner/repo/wiki/file`) or repository (`/owner/repo/blob/...`).
+fn github_path_to_raw(path: &str) -> Option<String> {
+    let mut segments = path.strip_prefix('/')?.splitn(4, '/');
+    match (
+        segments.next(),
+        segments.next(),
+        segments.next(),
+        segments.next(),
+    ) {
+        (Some(owner), Some(repo), Some("wiki"), Some(file)) if !file.is_empty() => {
+            Some(raw_wiki_file_url(owner, repo, file))
+        }
+        (Some(owner), Some(repo), Some("blob"), Some(rest)) => {
+            Some(format!("{GITHUB}/{owner}/{repo}/raw/{rest}"))
+        }
+        _ => None,
+    }
+}
+
+/// `https://github.com/owner/repo/blob/...` views are HTML pages; `raw` serves the file itself.
+pub fn github_blob_to_raw(url: &str) -> Option<String> {
+    let path = url.strip_prefix(GITHUB)?;
+    path.split('/')
+        .nth(3)
+        .filter(|kind| *kind == "blob")
+        .and_then(|_| github_path_to_raw(path))
+}
+
+// Relative references are relative to `/owner/repo/wiki/`, the flat directory of
+// every page, so `images/a.png`, `./images/a.png` and `wiki/images/a.png` from the
+// Home page all name the same file.
+fn relative_wiki_path(reference: &str) -> &str {
+    let reference = reference.trim_start_matches("./");
+    reference
+        .strip_prefix("../wiki/")
+        .or_else(|| reference.strip_prefix("wiki/"))
+        .unwrap_or(reference)
+}
+
+/// Where an `<img src>` in mirrored content should point, or `None` to leave it alone.
+///
+/// Images in the wiki repository are served from `raw.githubusercontent.com` instead of
+/// relying on github.com redirects. Other hosts, such as `user-attachments` and camo
+/// URLs, are left untouched.
+pub fn resolve_image_src(src: &str, account: &str, repository: &str) -> Option<String> {
+    if let Some(path) = src
+        .strip_prefix(GITHUB)
+        .filter(|path| path.starts_with('/'))
+    {
+        return github_path_to_raw(path);
+    }
+    if is_absolute(src) || src.starts_with("data:") || src.is_empty() {
+        return None;
+    }
+    if src.starts_with('/') {
+        return github_path_to_raw(src).or_else(|| Some(format!("{GITHUB}{src}")));
+    }
+    Some(raw_wiki_file_url(
+        account,
+        repository,
+        relative_wiki_path(src),
+    ))
+}
+
+/// Where a link to a non-page file (PDFs, archives, images...) should point, or `None`
+/// if the link is not to such a file in a wiki or repository.
+pub fn resolve_attachment_href(href: &str, account: &str, repository: &str) -> Option<String> {
+    if !has_attachment_extension(href) {
+        return None;
+    }
+    if let Some(path) = href
+        .strip_prefix(GITHUB)
+        .filter(|path| path.starts_with('/'))
+    {
+        return github_path_to_raw(path);
+    }
+    if is_absolute(href) || href.starts_with('#') || href.contains(':') {
+        return None;
+    }
+    if href.starts_with('/') {
+        return github_path_to_raw(href);
+    }
+    Some(raw_wiki_file_url(
+        account,
+        repository,
+        relative_wiki_path(href),
+    ))
+}
+
+/// Absolute mirror URL of `target` (`Page`, `Page#anchor`...) in `account/repository`'s wiki.
+///
+/// Pages link to each other relative to the wiki, so they are resolved here rather than
+/// by the browser, whose result depends on whether it is on `/wiki` or `/wiki/Page`.
+pub fn mirror_wiki_url(account: &str, repository: &str, target: &str) -> String {
+    let base = format!("/m/{account}/{repository}/wiki");
+    let page_end = target.find(['?', '#']).unwrap_or(target.len());
+    match &target[..page_end] {
+        "" | "Home" => format!("{base}{}", &target[page_end..]),
+        _ => format!("{base}/{target}"),
+    }
+}
+
+/// What a link in mirrored content points at.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LinkTarget<'a> {
+    /// A page of any GitHub wiki, as its github.com path, e.g. `/a/r/wiki/Page#anchor`.
+    WikiPage(String),
+    /// A page of the wiki being mirrored, relative to the wiki, e.g. `Page#anchor`.
+    SameWikiPage(&'a str),
+    /// An anchor on the current page.
+    Anchor,
+    /// Anything else on github.com, such as repository code, as an absolute URL.
+    GitHub(String),
+    /// A link off GitHub.
+    External,
+    /// `mailto:` and other schemes.
+    Other,
+}
+
+fn is_wiki_page_path(path: &str) -> bool {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let mut segments = path.split('/').skip(1);
+    let (Some(owner), Some(repo), Some("wiki")) =
+        (segments.next(), segments.next(), segments.next())
+    else {
+        return false;
+    };
+    !owner.is_empty()
+        && !repo.is_empty()
+        && match (segments.next(), segments.next()) {
+            (None, _) | (Some(""), None) => true,
+            // Special pages like `_history` are not mirrored.
+            (Some(page), None) => !page.starts_with('_'),
+            _ => false,
+        }
+}
+
+fn github_path_target<'a>(path: &str) -> LinkTarget<'a> {
+    if is_wiki_page_path(path) {
+        LinkTarget::WikiPage(path.to_string())
+    } else {
+        LinkTarget::GitHub(format!("{GITHUB}{path}"))
+    }
+}
+
+fn has_scheme(href: &str) -> bool {
+    href.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// Classify an `<a href>` found on a page of `account/repository`'s wiki.
+pub fn classify_link<'a>(href: &'a str, account: &str, repository: &str) -> LinkTarget<'a> {
+    if href.starts_with('#') {
+        return LinkTarget::Anchor;
+    }
+    for github in [
+        "https://github.com",
+        "http://github.com",
+        "//github.com",
+        "https://www.github.com",
+        "http://www.github.com",
+    ] {
+        if let Some(rest) = href.strip_prefix(github) {
+            if rest.is_empty() || rest.starts_with(['/', '?', '#']) {
+                return github_path_target(&format!("/{}", rest.trim_start_matches('/')));
+            }
+        }
+    }
+    if is_absolute(href) {
+        return LinkTarget::External;
+    }
+    if has_scheme(href) {
+        return LinkTarget::Other;
+    }
+    if href.starts_with('/') {
+        return github_path_target(href);
+    }
+
+    let relative = relative_wiki_path(href);
+    if relative.is_empty() {
+        LinkTarget::WikiPage(format!("/{account}/{repository}/wiki"))
+    } else if let Some(repository_path) = relative.strip_prefix("../") {
+        // From `/a/r/wiki/Page`, `../blob/...` is in the repository itself.
+        LinkTarget::GitHub(format!("{GITHUB}/{account}/{repository}/{repository_path}"))
+    } else {
+        LinkTarget::SameWikiPage(relative)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_sources() {
+        let cases = [
+            (
+                "images/a.png",
+                Some("https://raw.githubusercontent.com/wiki/o/r/images/a.png"),
+            ),
+            (
+                "./images/a.png",
+                Some("https://raw.githubusercontent.com/wiki/o/r/images/a.png"),
+            ),
+            (
+                "wiki/images/a.png",
+                Some("https://raw.githubusercontent.com/wiki/o/r/images/a.png"),
+            ),
+            (
+                "/x/y/wiki/images/a.png",
+                Some("https://raw.githubusercontent.com/wiki/x/y/images/a.png"),
+            ),
+            (
+                "https://github.com/x/y/wiki/a.png",
+                Some("https://raw.githubusercontent.com/wiki/x/y/a.png"),
+            ),
+            (
+                "https://github.com/x/y/blob/main/docs/a.png",
+                Some("https://github.com/x/y/raw/main/docs/a.png"),
+            ),
+            (
+                "/x/y/blob/main/a.png",
+                Some("https://github.com/x/y/raw/main/a.png"),
+            ),
+            (
+                "/x/y/raw/main/a.png",
+                Some("https://github.com/x/y/raw/main/a.png"),
+            ),
+            (
+                "https://github.com/user-attachments/assets/0c8d1a5e-a.png",
+                None,
+            ),
+            ("https://camo.githubusercontent.com/abc/def", None),
+            ("https://github.com/x/y/raw/main/a.png", None),
+            ("//example.com/a.png", None),
+            ("data:image/png;base64,AAAA", None),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(
+                resolve_image_src(src, "o", "r").as_deref(),
+                expected,
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn attachment_hrefs() {
+        let cases = [
+            (
+                "files/manual.pdf",
+                Some("https://raw.githubusercontent.com/wiki/o/r/files/manual.pdf"),
+            ),
+            (
+                "/x/y/wiki/release.ZIP",
+                Some("https://raw.githubusercontent.com/wiki/x/y/release.ZIP"),
+            ),
+            (
+                "https://github.com/x/y/wiki/files/a.pdf",
+                Some("https://raw.githubusercontent.com/wiki/x/y/files/a.pdf"),
+            ),
+            (
+                "https://github.com/x/y/blob/main/dist/tool.zip",
+                Some("https://github.com/x/y/raw/main/dist/tool.zip"),
+            ),
+            ("https://github.com/user-attachments/files/123/a.pdf", None),
+            ("https://example.com/a.pdf", None),
+            ("mailto:someone@example.com.txt", None),
+            ("Some-Page", None),
+            ("Version-1.0", None),
+            ("/x/y/wiki/Some-Page", None),
+            ("https://github.com/x/y/blob/main/src/main.rs", None),
+        ];
+        for (href, expected) in cases {
+            assert_eq!(
+                resolve_attachment_href(href, "o", "r").as_deref(),
+                expected,
+                "{href}"
+            );
+        }
+    }
+
+    #[test]
+    fn mirror_wiki_urls() {
+        assert_eq!(mirror_wiki_url("o", "r", "Page"), "/m/o/r/wiki/Page");
+        assert_eq!(mirror_wiki_url("o", "r", "Page#a"), "/m/o/r/wiki/Page#a");
+        assert_eq!(mirror_wiki_url("o", "r", "Home"), "/m/o/r/wiki");
+        assert_eq!(mirror_wiki_url("o", "r", "Home#toc"), "/m/o/r/wiki#toc");
+        assert_eq!(
+            mirror_wiki_url("o", "r", "Homebrew"),
+            "/m/o/r/wiki/Homebrew"
+        );
+    }
+
+    #[test]
+    fn classify_links() {
+        use LinkTarget::*;
+
+        let cases = [
+            ("#usage", Anchor),
+            ("Page", SameWikiPage("Page")),
+            ("./Page#install", SameWikiPage("Page#install")),
+            ("../wiki/Page", SameWikiPage("Page")),
+            ("wiki/Page", SameWikiPage("Page")),
+            ("./", WikiPage("/o/r/wiki".to_string())),
+            ("/x/y/wiki", WikiPage("/x/y/wiki".to_string())),
+            ("/x/y/wiki/", WikiPage("/x/y/wiki/".to_string())),
+            (
+                "https://github.com/x/y/wiki/Page#a",
+                WikiPage("/x/y/wiki/Page#a".to_string()),
+            ),
+            (
+                "http://www.github.com/x/y/wiki/Page",
+                WikiPage("/x/y/wiki/Page".to_string()),
+            ),
+            ("//github.com/x/y/wiki", WikiPage("/x/y/wiki".to_string())),
+            (
+                "https://github.com/x/y/wiki/_history",
+                GitHub("https://github.com/x/y/wiki/_history".to_string()),
+            ),
+            (
+                "https://github.com/x/y/tree/main/src",
+                GitHub("https://github.com/x/y/tree/main/src".to_string()),
+            ),
+            (
+                "/x/y/blob/main/README.md",
+                GitHub("https://github.com/x/y/blob/main/README.md".to_string()),
+            ),
+            (
+                "../issues/12",
+                GitHub("https://github.com/o/r/issues/12".to_string()),
+            ),
+            ("https://github.community/t/1", External),
+            ("https://example.com/x/y/wiki/Page", External),
+            ("//example.com", External),
+            ("mailto:someone@example.com", Other),
+        ];
+        for (href, expected) in cases {
+            assert_eq!(classify_link(href, "o", "r"), expected, "{href}");
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use lol_html::{element, html_content::Element, HtmlRewriter, Settings};
+use thiserror::Error;
+
+use crate::export::{background_file, render_index_file, render_page_file, ExportFile, PageFiles};
+use crate::links::raw_wiki_file_url;
+use crate::retrieval::{content_constructor_for_extension, ContentConstructor, ContentError};
+use crate::scraper::RenderOptions;
+use crate::wiki_page::{sort_and_dedup, WikiPageRef};
+
+#[derive(Debug, Error)]
+pub enum RenderError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Content(#[from] ContentError),
+}
+
+/// Paths of the files in `dir`, relative to `root`, leaving out `.git` and other dot files.
+///
+/// The directory `skip`, given canonicalized, is left out too.
+fn collect_files(
+    root: &Path,
+    dir: &Path,
+    skip: Option<&Path>,
+    files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            if skip.is_some_and(|skip| path.canonicalize().is_ok_and(|path| path == skip)) {
+                continue;
+            }
+            collect_files(root, &path, skip, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            files.push(relative.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// Page slug and markup of a page source file, e.g. `Setup-Guide.md`.
+fn page_source(path: &Path) -> Option<(String, ContentConstructor)> {
+    let stem = path.file_stem()?.to_str()?;
+    let constructor = content_constructor_for_extension(path.extension()?.to_str()?)?;
+    Some((stem.replace(' ', "-"), constructor))
+}
+
+/// Point links and images at files of the checkout instead of GitHub's copies.
+fn use_local_files(html: &[u8], account: &str, repository: &str) -> Vec<u8> {
+    let raw_prefix = raw_wiki_file_url(account, repository, "");
+    let localize = |el: &mut Element, attribute: &str| {
+        if let Some(url) = el.get_attribute(attribute) {
+            if let Some(path) = url.strip_prefix(&raw_prefix) {
+                el.set_attribute(attribute, path).unwrap();
+            }
+        }
+    };
+
+    let mut output = Vec::new();
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![
+                element!("a[href]", |el: &mut Element| {
+                    localize(el, "href");
+                    Ok(())
+                }),
+                element!("img[src]", |el: &mut Element| {
+                    localize(el, "src");
+                    Ok(())
+                }),
+            ],
+            ..Settings::default()
+        },
+        |c: &[u8]| output.extend_from_slice(c),
+    );
+
+    rewriter.write(html).unwrap();
+    rewriter.end().unwrap();
+
+    output
+}
+
+/// Render a cloned `<repository>.wiki` directory into `output` as the mirror would show it.
+///
+/// Other files of the checkout, such as images, are copied next to the pages. Returns the
+/// number of pages rendered.
+pub fn render_wiki_directory(
+    dir: &Path,
+    output: &Path,
+    account: &str,
+    repository: &str,
+    options: &RenderOptions,
+    base_url: &str,
+) -> Result<usize, RenderError> {
+    // Output from an earlier render inside the checkout is not part of the wiki.
+    let previous_output = output.canonicalize().ok();
+    let mut files = Vec::new();
+    collect_files(dir, dir, previous_output.as_deref(), &mut files)?;
+    files.sort();
+
+    let mut sources = Vec::new();
+    let mut assets = Vec::new();
+    for file in files {
+        match page_source(&file) {
+            // `_Sidebar`, `_Footer` and the like are not pages.
+            Some((slug, _)) if slug.starts_with('_') => {}
+            Some((slug, constructor)) => sources.push((slug, file, constructor)),
+            None => assets.push(file),
+        }
+    }
+
+    let pages = sort_and_dedup(
+        sources
+            .iter()
+            .map(|(slug, _, _)| WikiPageRef {
+                account: account.to_string(),
+                repository: repository.to_string(),
+                slug: slug.clone(),
+                title: slug.replace('-', " "),
+            })
+            .collect(),
+    );
+    let page_files = PageFiles::new(&pages);
+
+    let mut rendered = vec![
+        render_index_file(account, repository, &pages, &page_files, options, base_url)?,
+        background_file(),
+    ];
+    for page in &pages {
+        let Some((_, file, constructor)) = sources.iter().find(|(slug, _, _)| *slug == page.slug)
+        else {
+            continue;
+        };
+        let content = constructor(std::fs::read_to_string(dir.join(file))?);
+        let ExportFile { path, contents } =
+            render_page_file(page, content, &pages, &page_files, options, base_url)?;
+        rendered.push(ExportFile {
+            path,
+            contents: use_local_files(&contents, account, repository),
+        });
+    }
+
+    std::fs::create_dir_all(output)?;
+    for file in &rendered {
+        std::fs::write(output.join(&file.path), &file.contents)?;
+    }
+    for asset in &assets {
+        let target = output.join(asset);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(dir.join(asset), target)?;
+    }
+
+    Ok(pages.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_checkout() {
+        let output = std::env::temp_dir().join(format!("local-wiki-{}", std::process::id()));
+        let count = render_wiki_directory(
+            Path::new("test-data/local-wiki"),
+            &output,
+            "a",
+            "r",
+            &RenderOptions::default(),
+            "https://example.com",
+        )
+        .unwrap();
+        assert_eq!(count, 3);
+
+        let home = std::fs::read_to_string(output.join("Home.html")).unwrap();
+        assert!(home.contains("<a href=\"Setup-Guide.html\">Setup Guide</a>"));
+        assert!(home.contains("<a href=\"Release-Notes.html\">release notes</a>"));
+        assert!(home.contains("<img src=\"images/logo.svg\" alt=\"Logo\""));
+        assert!(home.contains("<a rel=\"next\" href=\"Release-Notes.html\">"));
+
+        let notes = std::fs::read_to_string(output.join("Release-Notes.html")).unwrap();
+        assert!(notes.contains("does not render Textile"));
+        assert!(output.join("_index.html").exists());
+        assert!(output.join("images/logo.svg").exists());
+        assert!(!output.join("_Sidebar.html").exists());
+
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn renders_into_checkout() {
+        let dir = std::env::temp_dir().join(format!("local-wiki-inside-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Home.md"), "See [[index]].").unwrap();
+        std::fs::write(dir.join("index.md"), "# Index page").unwrap();
+        let output = dir.join("preview");
+
+        for _ in 0..2 {
+            let count = render_wiki_directory(
+                &dir,
+                &output,
+                "a",
+                "r",
+                &RenderOptions::default(),
+                "https://example.com",
+            )
+            .unwrap();
+            assert_eq!(count, 2);
+        }
+
+        let index = std::fs::read_to_string(output.join("_index.html")).unwrap();
+        assert!(index.contains("href=\"index.html\""));
+        let page = std::fs::read_to_string(output.join("index.html")).unwrap();
+        assert!(page.contains("Index page"));
+        assert!(!output.join("preview").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
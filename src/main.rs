@@ -1,23 +1,56 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
 use std::time::Duration;
 
 use reqwest::Client;
-use retrieval::{retrieve_wiki_sitemap_index, Content};
-use rocket::http::{ContentType, Method, Status};
+use retrieval::{
+    cached_wiki_page_listing, content_constructor_for_extension, known_last_updated,
+    known_page_title, known_wikis, raw_wiki_source_url, remember_wiki, retrieve_wiki_sitemap_index,
+    wiki_page_listing_within, Content, ContentConstructor,
+};
+use rocket::fairing::AdHoc;
+use rocket::http::{ContentType, Header, Method, Status};
+use rocket::request::FromParam;
 use rocket::response::{content, status};
 use rocket::response::{Redirect, Responder};
 use rocket::route::{Handler, Outcome};
-use rocket::{catch, catchers, get, launch, routes, Build, Rocket, Route, State};
+use rocket::{catch, catchers, error, get, routes, warn, Build, Rocket, Route, State};
 
 use crate::scraper::process_html;
 use askama::Template;
+use clap::{Parser, Subcommand};
 
+use crate::config::Config;
+use crate::content_store::Snapshot;
 use crate::gh_extensions::github_wiki_markdown_to_pure_markdown;
-use crate::scraper::process_markdown;
+use crate::page_meta::{PageInfo, PageMeta};
+use crate::scraper::{process_markdown, sanitize_html, RenderOptions};
+use crate::search::{SearchIndex, SearchResult};
+use crate::sitemap::write_sitemap_index;
+use crate::toc::{add_heading_anchors, render_toc};
+use crate::wiki_page::{page_navigation, PageNavigation};
 
+mod api;
+mod book;
+mod cache;
+mod config;
+mod content_store;
 mod decommission;
+mod export;
 mod gh_extensions;
+mod highlight;
+mod image_proxy;
+mod links;
+mod local_wiki;
+mod page_meta;
 mod retrieval;
 mod scraper;
+mod search;
+mod sitemap;
+mod toc;
+mod warm_up;
+mod wiki_page;
 
 #[derive(Template)]
 #[template(path = "front_page.html")]
@@ -28,6 +61,8 @@ type HtmlErrorResponse = status::Custom<HtmlResponse>;
 type HtmlResult = Result<HtmlResponse, HtmlErrorResponse>;
 type StaticAssetResponse = (Status, (ContentType, &'static [u8]));
 
+/// How long a page view waits for its wiki's page listing before going without navigation.
+const NAVIGATION_LISTING_TIMEOUT: Duration = Duration::from_secs(2);
 const SITEMAP_BASE_URL: &str = "https://nelsonjchen.github.io/github-wiki-see-rs-sitemaps";
 
 fn render_template<T: Template>(template: &T) -> HtmlResult {
@@ -76,27 +111,31 @@ fn call_to_action_svg() -> StaticAssetResponse {
     )
 }
 
+#[derive(Template)]
+#[template(path = "robots.txt")]
+struct RobotsTemplate<'a> {
+    base_url: &'a str,
+}
+
 #[get("/robots.txt")]
-fn robots_txt(host: &rocket::http::uri::Host<'_>) -> StaticAssetResponse {
+fn robots_txt(
+    host: &rocket::http::uri::Host<'_>,
+    config: &State<Config>,
+) -> Result<content::RawText<String>, status::Custom<String>> {
     // Check if the host is an IP address, if so, don't allow crawling
     let domain = host.domain().as_str();
     // Need to check if the host is an IP address
     if domain.parse::<std::net::IpAddr>().is_ok() {
-        return (
-            Status::Ok,
-            (
-                ContentType::Plain,
-                include_bytes!("../templates/robots_ip.txt"),
-            ),
-        );
+        return Ok(content::RawText(
+            include_str!("../templates/robots_ip.txt").to_string(),
+        ));
     }
-    (
-        Status::Ok,
-        (
-            ContentType::Plain,
-            include_bytes!("../templates/robots.txt"),
-        ),
-    )
+    RobotsTemplate {
+        base_url: config.base_url(),
+    }
+    .render()
+    .map(content::RawText)
+    .map_err(|error| status::Custom(Status::InternalServerError, error.to_string()))
 }
 
 #[get("/sitemap.xml")]
@@ -145,25 +184,74 @@ impl From<RemoveSlashes> for Vec<Route> {
 }
 
 #[get("/debug_sitemaps/<account>/<repository>/sitemap.xml")]
-async fn wiki_debug_sitemaps(
+fn wiki_debug_sitemaps(account: &str, repository: &str) -> Redirect {
+    Redirect::permanent(format!("/m/{account}/{repository}/sitemap.xml"))
+}
+
+#[get("/<account>/<repository>/sitemap.xml")]
+async fn wiki_sitemap(
     account: &str,
     repository: &str,
     client: &State<Client>,
+    config: &State<Config>,
 ) -> Result<content::RawXml<String>, status::Custom<String>> {
-    let content = retrieve_wiki_sitemap_index(account, repository, client)
+    let content = retrieve_wiki_sitemap_index(account, repository, client, config.base_url())
         .await
         .map_err(|error| status::Custom(Status::InternalServerError, format!("Error: {error}")))?;
 
     Ok(content::RawXml(content))
 }
 
+// Self-hosted index of the per-wiki sitemaps for wikis mirrored recently.
+#[get("/wiki_sitemap_index.xml")]
+fn wiki_sitemap_index(
+    config: &State<Config>,
+) -> Result<content::RawXml<String>, status::Custom<String>> {
+    let base_url = config.base_url();
+    let sitemap_urls: Vec<String> = known_wikis()
+        .into_iter()
+        .map(|(account, repository)| format!("{base_url}/m/{account}/{repository}/sitemap.xml"))
+        .collect();
+    let content = write_sitemap_index(&sitemap_urls)
+        .map_err(|error| status::Custom(Status::InternalServerError, format!("Error: {error}")))?;
+
+    Ok(content::RawXml(content))
+}
+
 #[derive(Template)]
 #[template(path = "mirror.html")]
 struct MirrorTemplate {
     original_title: String,
     original_url: String,
     mirrored_content: String,
+    /// Table of contents for `mirrored_content`, if it has enough headings.
+    toc: Option<String>,
     index_url: String,
+    search_url: String,
+    meta: Option<PageMeta>,
+    breadcrumbs: Vec<Breadcrumb>,
+    navigation: Option<PageNavigation>,
+    /// When the stored copy being shown instead of the live page was fetched.
+    snapshot_date: Option<String>,
+}
+
+struct Breadcrumb {
+    name: String,
+    /// `None` for the current page.
+    url: Option<String>,
+}
+
+fn wiki_breadcrumbs(account: &str, repository: &str, current: String) -> Vec<Breadcrumb> {
+    vec![
+        Breadcrumb {
+            name: format!("{account}/{repository} wiki"),
+            url: Some(format!("/m/{account}/{repository}/wiki")),
+        },
+        Breadcrumb {
+            name: current,
+            url: None,
+        },
+    ]
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -191,8 +279,10 @@ async fn mirror_home(
     account: &str,
     repository: &str,
     client: &State<Client>,
+    config: &State<Config>,
+    search: &State<Arc<SearchIndex>>,
 ) -> Result<HtmlResponse, MirrorError> {
-    mirror_page(account, repository, "Home", client).await
+    mirror_page(account, repository, "Home", client, config, search).await
 }
 
 // Copied from percent_encoding crate but modified for what GitHub is OK with.
@@ -247,23 +337,148 @@ fn github_wiki_url_encoded(account: &str, repository: &str, page: &str) -> Strin
     )
 }
 
+fn mirror_page_url(base_url: &str, account: &str, repository: &str, page: &str) -> String {
+    if page == "Home" {
+        format!("{base_url}/m/{account}/{repository}/wiki")
+    } else {
+        format!(
+            "{base_url}/m/{account}/{repository}/wiki/{}",
+            percent_encoding::utf8_percent_encode(page, NON_ALPHANUMERIC_GH),
+        )
+    }
+}
+
 fn mirror_index_url(account: &str, repository: &str) -> String {
     format!("/m/{account}/{repository}/wiki_index")
 }
 
+fn mirror_search_url(account: &str, repository: &str) -> String {
+    format!("/m/{account}/{repository}/search")
+}
+
+// GitHub's title for the page when we have seen it, otherwise a guess from the slug.
+fn wiki_page_name(account: &str, repository: &str, page: &str) -> String {
+    known_page_title(account, repository, page).unwrap_or_else(|| page.replace('-', " "))
+}
+
 fn wiki_page_title(account: &str, repository: &str, page: &str) -> String {
     format!(
         "{} - {account}/{repository} GitHub Wiki",
-        page.replace('-', " ")
+        wiki_page_name(account, repository, page)
     )
 }
 
-#[get("/<account>/<repository>/wiki/<page>", rank = 2)]
+/// `<page>.<extension>` path segment naming a page source file, e.g. `Home.md`.
+struct SourceFileName<'r> {
+    /// The whole path segment, which may also be the slug of a page.
+    name: &'r str,
+    page: &'r str,
+    extension: &'r str,
+    enum_constructor: ContentConstructor,
+}
+
+impl<'r> FromParam<'r> for SourceFileName<'r> {
+    type Error = &'r str;
+
+    fn from_param(param: &'r str) -> Result<Self, Self::Error> {
+        let (page, extension) = param.rsplit_once('.').ok_or(param)?;
+        let enum_constructor = content_constructor_for_extension(extension).ok_or(param)?;
+        if page.is_empty() {
+            return Err(param);
+        }
+        Ok(Self {
+            name: param,
+            page,
+            extension,
+            enum_constructor,
+        })
+    }
+}
+
+#[derive(Responder)]
+struct RawSourceResponse {
+    source: (ContentType, String),
+    cache_control: Header<'static>,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder)]
+enum RawSourceError {
+    Status(status::Custom<String>),
+    GiveUpSendToGitHub(Redirect),
+    Page(Result<HtmlResponse, MirrorError>),
+}
+
+fn source_content_type(content: &Content) -> ContentType {
+    match content {
+        Content::Markdown(_) => {
+            ContentType::new("text", "markdown").with_params(("charset", "utf-8"))
+        }
+        _ => ContentType::Plain,
+    }
+}
+
+#[get("/<account>/<repository>/wiki/<file>", rank = 2)]
+async fn mirror_page_source(
+    account: &str,
+    repository: &str,
+    file: SourceFileName<'_>,
+    client: &State<Client>,
+    config: &State<Config>,
+    search: &State<Arc<SearchIndex>>,
+) -> Result<RawSourceResponse, RawSourceError> {
+    use retrieval::retrieve_source_file_extension;
+    use retrieval::ContentError;
+    use RawSourceError::*;
+
+    let content = retrieve_source_file_extension(
+        account,
+        repository,
+        file.page,
+        client,
+        file.enum_constructor,
+        file.extension,
+    )
+    .await
+    .map_err(|e| match e {
+        // No such source file, so it may be a page whose name ends like one, e.g. `Notes.md`.
+        ContentError::NotFound => None,
+        ContentError::TooMayRequests => Some(GiveUpSendToGitHub(Redirect::temporary(
+            raw_wiki_source_url(account, repository, file.page, file.extension),
+        ))),
+        ContentError::Decommissioned => Some(GiveUpSendToGitHub(Redirect::permanent(
+            github_wiki_url_encoded(account, repository, file.page),
+        ))),
+        ContentError::OtherError(e) => Some(Status(status::Custom(
+            rocket::http::Status::InternalServerError,
+            format!("500 Internal Server Error - {e}"),
+        ))),
+    });
+    let content = match content {
+        Ok(content) => content,
+        Err(Some(error)) => return Err(error),
+        Err(None) => {
+            return Err(Page(
+                mirror_page(account, repository, file.name, client, config, search).await,
+            ))
+        }
+    };
+
+    let content_type = source_content_type(&content);
+    Ok(RawSourceResponse {
+        source: (content_type, content.source().to_string()),
+        cache_control: Header::new("Cache-Control", "public, max-age=600"),
+    })
+}
+
+#[get("/<account>/<repository>/wiki/<page>", rank = 3)]
 async fn mirror_page(
     account: &str,
     repository: &str,
     page: &str,
     client: &State<Client>,
+    config: &State<Config>,
+    search: &State<Arc<SearchIndex>>,
 ) -> Result<HtmlResponse, MirrorError> {
     use retrieval::retrieve_source_file;
     use retrieval::ContentError;
@@ -274,31 +489,95 @@ async fn mirror_page(
 
     // Rocket's Redirect / GitHub itself doesn't like unencoded URLs.
     let original_url_encoded = github_wiki_url_encoded(account, repository, page);
-    let page_title = wiki_page_title(account, repository, page);
+
+    if let Some(snapshot) = warmed_page(config, account, repository, page).await {
+        let navigation = page_listing_navigation(account, repository, page, client).await;
+        return render_snapshot(config, account, repository, page, snapshot, navigation)
+            .map_err(InternalError);
+    }
 
     // Grab main content from GitHub
     // Consider it "fatal" if this doesn't exist/errors and forward to GitHub or return an error.
-    let content = retrieve_source_file(account, repository, page, client)
-        .await
-        .map_err(|e| match e {
-            ContentError::NotFound => {
-                GiveUpSendToGitHub(Redirect::to(original_url_encoded.clone()))
+    let content = match retrieve_source_file(account, repository, page, client).await {
+        Ok(content) => content,
+        Err(e) => {
+            // Rather than an error, show the last copy that was fetched, if any.
+            if matches!(
+                e,
+                ContentError::OtherError(_) | ContentError::TooMayRequests
+            ) {
+                if let Some(snapshot) = stored_page(config, account, repository, page) {
+                    // GitHub is failing, so only a listing it already gave us will do.
+                    let navigation = cached_wiki_page_listing(account, repository)
+                        .and_then(|listing| page_navigation(&listing.pages, page));
+                    return render_snapshot(
+                        config, account, repository, page, snapshot, navigation,
+                    )
+                    .map_err(InternalError);
+                }
             }
-            ContentError::TooMayRequests => {
-                GiveUpSendToGitHub(Redirect::temporary(original_url_encoded.clone()))
-            }
-            ContentError::Decommissioned => {
-                GiveUpSendToGitHub(Redirect::permanent(original_url_encoded.clone()))
-            }
-            ContentError::OtherError(e) => mirror_internal_error(MirrorTemplate {
-                original_title: page_title.clone(),
-                original_url: original_url.clone(),
-                mirrored_content: format!("500 Internal Server Error - {e}"),
-                index_url: mirror_index_url(account, repository),
-            }),
-        })?;
+            return Err(match e {
+                ContentError::NotFound => {
+                    GiveUpSendToGitHub(Redirect::to(original_url_encoded.clone()))
+                }
+                ContentError::TooMayRequests => {
+                    GiveUpSendToGitHub(Redirect::temporary(original_url_encoded.clone()))
+                }
+                ContentError::Decommissioned => {
+                    GiveUpSendToGitHub(Redirect::permanent(original_url_encoded.clone()))
+                }
+                ContentError::OtherError(e) => mirror_internal_error(MirrorTemplate {
+                    original_title: wiki_page_title(account, repository, page),
+                    original_url: original_url.clone(),
+                    mirrored_content: format!("500 Internal Server Error - {e}"),
+                    toc: None,
+                    index_url: mirror_index_url(account, repository),
+                    search_url: mirror_search_url(account, repository),
+                    meta: None,
+                    breadcrumbs: wiki_breadcrumbs(
+                        account,
+                        repository,
+                        wiki_page_name(account, repository, page),
+                    ),
+                    navigation: None,
+                    snapshot_date: None,
+                }),
+            });
+        }
+    };
+
+    remember_wiki(account, repository);
+    // Retrieval may have found the real title.
+    let page_title = wiki_page_title(account, repository, page);
+
+    let format = content.format();
+    let source = content.source().to_string();
+    let html = content_to_html(content, account, repository, &config.render_options());
+    if let Some(store) = config.content_store() {
+        let snapshot = Snapshot::new(page_title.clone(), format, source, html.clone());
+        // Not awaited: the live page is worth showing whether or not it can be stored.
+        store.save_in_background(account, repository, page, snapshot);
+    }
+    let (original_html, headings) = add_heading_anchors(&html);
 
-    let original_html = content_to_html(content, account, repository, page);
+    search.index_page(
+        account,
+        repository,
+        page,
+        &wiki_page_name(account, repository, page),
+        &original_html,
+    );
+
+    let meta = mirror_page_meta(
+        config,
+        account,
+        repository,
+        page,
+        &page_title,
+        &original_html,
+    );
+
+    let navigation = page_listing_navigation(account, repository, page, client).await;
 
     // The content exists. Now try to get the sidebar.
     //
@@ -309,8 +588,8 @@ async fn mirror_page(
     //     .ok();
     let sidebar_content = None;
 
-    let sidebar_html =
-        sidebar_content.map(|content| content_to_html(content, account, repository, page));
+    let sidebar_html = sidebar_content
+        .map(|content| content_to_html(content, account, repository, &config.render_options()));
 
     // Append the sidebar if it exists
     let mirrored_content = if let Some(sidebar_html) = sidebar_html {
@@ -327,16 +606,125 @@ async fn mirror_page(
         original_title: page_title.clone(),
         original_url: original_url_encoded.clone(),
         mirrored_content,
+        toc: render_toc(&headings),
         index_url: mirror_index_url(account, repository),
+        search_url: mirror_search_url(account, repository),
+        meta: Some(meta),
+        breadcrumbs: wiki_breadcrumbs(
+            account,
+            repository,
+            wiki_page_name(account, repository, page),
+        ),
+        navigation,
+        snapshot_date: None,
     })
     .map_err(InternalError)
 }
 
+fn stored_page(config: &Config, account: &str, repository: &str, page: &str) -> Option<Snapshot> {
+    config.content_store()?.load(account, repository, page)
+}
+
+/// Canonical link, description and structured data of a mirrored page.
+fn mirror_page_meta(
+    config: &Config,
+    account: &str,
+    repository: &str,
+    page: &str,
+    title: &str,
+    html: &str,
+) -> PageMeta {
+    let mirror_home_url = mirror_page_url(config.base_url(), account, repository, "Home");
+    let mirror_url = mirror_page_url(config.base_url(), account, repository, page);
+    let date_modified = known_last_updated(account, repository, page);
+    PageMeta::new(
+        &PageInfo {
+            account,
+            repository,
+            page_name: &wiki_page_name(account, repository, page),
+            title,
+            mirror_home_url: &mirror_home_url,
+            mirror_url: &mirror_url,
+            original_url: &github_wiki_url_encoded(account, repository, page),
+            date_modified: date_modified.as_deref(),
+        },
+        html,
+        config.canonical,
+    )
+}
+
+/// Links to the pages around `page`, from the wiki's page listing.
+///
+/// Large wikis take several requests to list, so a view only waits for the listing
+/// briefly and leaves the rest of the fetch to fill the cache for later views.
+async fn page_listing_navigation(
+    account: &str,
+    repository: &str,
+    page: &str,
+    client: &Client,
+) -> Option<PageNavigation> {
+    let listing =
+        wiki_page_listing_within(account, repository, client, NAVIGATION_LISTING_TIMEOUT).await?;
+    page_navigation(&listing.pages, page)
+}
+
+/// The copy of a page the last warm-up stored, unless the next warm-up is due.
+async fn warmed_page(
+    config: &Config,
+    account: &str,
+    repository: &str,
+    page: &str,
+) -> Option<Snapshot> {
+    let store = config.content_store()?;
+    let (account, repository, page) = (
+        account.to_string(),
+        repository.to_string(),
+        page.to_string(),
+    );
+    let snapshot = tokio::task::spawn_blocking(move || store.load(&account, &repository, &page))
+        .await
+        .ok()??;
+    let period = Duration::from_secs(config.warm_up_period_hours * 60 * 60);
+    (snapshot.warmed && snapshot.age() < period).then_some(snapshot)
+}
+
+// A stored copy of a page: a warmed up one, or the last one fetched when GitHub fails.
+fn render_snapshot(
+    config: &Config,
+    account: &str,
+    repository: &str,
+    page: &str,
+    snapshot: Snapshot,
+    navigation: Option<PageNavigation>,
+) -> HtmlResult {
+    let (html, headings) = add_heading_anchors(&snapshot.html);
+    // Crawlers that come by while GitHub fails should not lose the canonical link.
+    let meta = mirror_page_meta(config, account, repository, page, &snapshot.title, &html);
+    render_template(&MirrorTemplate {
+        original_title: snapshot.title.clone(),
+        original_url: github_wiki_url_encoded(account, repository, page),
+        mirrored_content: html,
+        toc: render_toc(&headings),
+        index_url: mirror_index_url(account, repository),
+        search_url: mirror_search_url(account, repository),
+        meta: Some(meta),
+        breadcrumbs: wiki_breadcrumbs(
+            account,
+            repository,
+            wiki_page_name(account, repository, page),
+        ),
+        navigation,
+        // Warmed pages are as current as the warm-ups keep them.
+        snapshot_date: (!snapshot.warmed).then(|| snapshot.fetched_date()),
+    })
+}
+
 #[get("/<account>/<repository>/wiki_index")]
 async fn mirror_page_index(
     account: &str,
     repository: &str,
     client: &State<Client>,
+    config: &State<Config>,
 ) -> Result<HtmlResponse, MirrorError> {
     use retrieval::retrieve_wiki_index;
     use retrieval::ContentError;
@@ -365,23 +753,96 @@ async fn mirror_page_index(
                 original_title: page_title.clone(),
                 original_url: original_url.clone(),
                 mirrored_content: format!("500 Internal Server Error - {e}"),
+                toc: None,
                 index_url: mirror_index_url(account, repository),
+                search_url: mirror_search_url(account, repository),
+                meta: None,
+                breadcrumbs: wiki_breadcrumbs(account, repository, "Page Index".to_string()),
+                navigation: None,
+                snapshot_date: None,
             }),
         })?;
 
-    let original_html = content_to_html(content, account, repository, "Home");
+    let original_html = content_to_html(content, account, repository, &config.render_options());
 
     render_template(&MirrorTemplate {
         original_title: page_title.clone(),
         original_url: original_url.clone(),
         mirrored_content: original_html,
+        toc: None,
         index_url: mirror_index_url(account, repository),
+        search_url: mirror_search_url(account, repository),
+        meta: None,
+        breadcrumbs: wiki_breadcrumbs(account, repository, "Page Index".to_string()),
+        navigation: None,
+        snapshot_date: None,
     })
     .map_err(InternalError)
 }
 
-fn content_to_html(content: Content, account: &str, repository: &str, page: &str) -> String {
-    match content {
+#[derive(Template)]
+#[template(path = "search.html")]
+struct SearchTemplate {
+    title: String,
+    /// Where the search form submits to.
+    action: String,
+    query: String,
+    results: Vec<SearchResult>,
+    index_url: Option<String>,
+}
+
+fn search_results(
+    search: &SearchIndex,
+    query: Option<&str>,
+    wiki: Option<(&str, &str)>,
+) -> Result<Vec<SearchResult>, HtmlErrorResponse> {
+    match query.map(str::trim) {
+        Some(query) if !query.is_empty() => search.search(query, wiki).map_err(|error| {
+            status::Custom(
+                Status::InternalServerError,
+                content::RawHtml(format!(
+                    "500 Internal Server Error - Search failed: {error}"
+                )),
+            )
+        }),
+        _ => Ok(Vec::new()),
+    }
+}
+
+#[get("/<account>/<repository>/search?<q>")]
+fn mirror_search(
+    account: &str,
+    repository: &str,
+    q: Option<&str>,
+    search: &State<Arc<SearchIndex>>,
+) -> HtmlResult {
+    render_template(&SearchTemplate {
+        title: format!("Search - {account}/{repository} GitHub Wiki"),
+        action: mirror_search_url(account, repository),
+        query: q.unwrap_or_default().to_string(),
+        results: search_results(search, q, Some((account, repository)))?,
+        index_url: Some(mirror_index_url(account, repository)),
+    })
+}
+
+#[get("/search?<q>")]
+fn global_search(q: Option<&str>, search: &State<Arc<SearchIndex>>) -> HtmlResult {
+    render_template(&SearchTemplate {
+        title: "Search mirrored GitHub Wikis".to_string(),
+        action: "/search".to_string(),
+        query: q.unwrap_or_default().to_string(),
+        results: search_results(search, q, None)?,
+        index_url: None,
+    })
+}
+
+fn content_to_html(
+    content: Content,
+    account: &str,
+    repository: &str,
+    options: &RenderOptions,
+) -> String {
+    let html = match content {
         Content::AsciiDoc(ascii_doc) => {
             let md = format!(
                 "🚨 **github-wiki-see.page does not render asciidoc. Source for crawling below. Please visit the Original URL!** 🚨\n
@@ -389,7 +850,7 @@ fn content_to_html(content: Content, account: &str, repository: &str, page: &str
 {ascii_doc}\n
 ```\n"
             );
-            process_markdown(&md, account, repository, page == "Home")
+            process_markdown(&md, account, repository, options)
         }
         Content::Creole(cr) => {
             let md = format!(
@@ -398,12 +859,12 @@ fn content_to_html(content: Content, account: &str, repository: &str, page: &str
 {cr}\n
 ```\n"
             );
-            process_markdown(&md, account, repository, page == "Home")
+            process_markdown(&md, account, repository, options)
         }
         Content::Markdown(md) => {
             // Markdown can have mediawiki links in them apparently
             let pure_markdown = github_wiki_markdown_to_pure_markdown(&md, account, repository);
-            process_markdown(&pure_markdown, account, repository, page == "Home")
+            process_markdown(&pure_markdown, account, repository, options)
         }
         Content::Mediawiki(mw) => {
             let md = format!(
@@ -412,7 +873,7 @@ fn content_to_html(content: Content, account: &str, repository: &str, page: &str
 {mw}\n
 ```\n"
             );
-            process_markdown(&md, account, repository, page == "Home")
+            process_markdown(&md, account, repository, options)
         }
         Content::Orgmode(og) => {
             let md = format!(
@@ -421,7 +882,7 @@ fn content_to_html(content: Content, account: &str, repository: &str, page: &str
 {og}\n
 ```\n"
             );
-            process_markdown(&md, account, repository, page == "Home")
+            process_markdown(&md, account, repository, options)
         }
         Content::Pod(p) => {
             let md = format!(
@@ -430,7 +891,7 @@ fn content_to_html(content: Content, account: &str, repository: &str, page: &str
 {p}\n
 ```\n"
             );
-            process_markdown(&md, account, repository, page == "Home")
+            process_markdown(&md, account, repository, options)
         }
         Content::Rdoc(rd) => {
             let md = format!(
@@ -439,7 +900,7 @@ fn content_to_html(content: Content, account: &str, repository: &str, page: &str
 {rd}\n
 ```\n"
             );
-            process_markdown(&md, account, repository, page == "Home")
+            process_markdown(&md, account, repository, options)
         }
         Content::Textile(tt) => {
             let md = format!(
@@ -448,7 +909,7 @@ fn content_to_html(content: Content, account: &str, repository: &str, page: &str
 {tt}\n
 ```\n"
             );
-            process_markdown(&md, account, repository, page == "Home")
+            process_markdown(&md, account, repository, options)
         }
         Content::ReStructuredText(rst) => {
             let md = format!(
@@ -457,13 +918,15 @@ fn content_to_html(content: Content, account: &str, repository: &str, page: &str
 {rst}\n
 ```\n"
             );
-            process_markdown(&md, account, repository, page == "Home")
+            process_markdown(&md, account, repository, options)
         }
         Content::FallbackHtml(html) => {
             let annotated_html = format!("{html} <h6>⚠️ **GitHub.com Fallback** ⚠️</h6>");
-            process_html(&annotated_html, account, repository, page == "Home")
+            process_html(&annotated_html, account, repository, options)
         }
-    }
+    };
+
+    sanitize_html(&html)
 }
 
 #[catch(404)]
@@ -483,15 +946,34 @@ fn healthz() -> &'static str {
     "ok"
 }
 
-#[launch]
+// Make queued pages searchable every `COMMIT_INTERVAL`, on a blocking thread.
+fn spawn_search_commits(search: Arc<SearchIndex>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(search::COMMIT_INTERVAL);
+        loop {
+            interval.tick().await;
+            let search = search.clone();
+            match tokio::task::spawn_blocking(move || search.commit()).await {
+                Ok(Ok(())) => {}
+                // Pages that failed are indexed again when next viewed.
+                Ok(Err(error)) => error!("Could not commit search index: {error}"),
+                Err(error) => error!("Search index commit panicked: {error}"),
+            }
+        }
+    });
+}
+
 fn rocket() -> Rocket<Build> {
     // Mount front Page
 
     let mut mirror_routes = routes![
         mirror_home,
         mirror_page_redirect_home,
+        mirror_page_source,
         mirror_page,
-        mirror_page_index
+        mirror_page_index,
+        mirror_search,
+        wiki_sitemap
     ];
     // Strip off trailing slashes on this route
     mirror_routes.push(Route::ranked(
@@ -503,8 +985,63 @@ fn rocket() -> Rocket<Build> {
 
     // Mount Mirror
     rocket::build()
+        .attach(AdHoc::config::<Config>())
+        .attach(AdHoc::try_on_ignite("Search index", |rocket| async {
+            let path = rocket
+                .state::<Config>()
+                .and_then(|config| config.search_index_path.clone());
+            match SearchIndex::open(path.as_deref()) {
+                Ok(search) => Ok(rocket.manage(Arc::new(search))),
+                Err(error) => {
+                    error!("Could not open search index: {error}");
+                    Err(rocket)
+                }
+            }
+        }))
+        .attach(AdHoc::on_liftoff("Search index commits", |rocket| {
+            let search = rocket.state::<Arc<SearchIndex>>().cloned();
+            Box::pin(async move {
+                if let Some(search) = search {
+                    spawn_search_commits(search);
+                }
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Warm-up", |rocket| {
+            let client = rocket.state::<Client>().cloned();
+            let config = rocket.state::<Config>().cloned();
+            let search = rocket.state::<Arc<SearchIndex>>().cloned();
+            Box::pin(async move {
+                let (Some(client), Some(config)) = (client, config) else {
+                    return;
+                };
+                let Some(seed) = config.warm_up_seed.clone() else {
+                    return;
+                };
+                tokio::spawn(async move {
+                    let period = Duration::from_secs(config.warm_up_period_hours * 60 * 60);
+                    loop {
+                        match warm_up::load_seed(&seed, &client).await {
+                            Ok(wikis) => {
+                                let warm_up = warm_up::WarmUp {
+                                    client: &client,
+                                    config: &config,
+                                    search: search.as_deref(),
+                                    sitemap_dir: None,
+                                };
+                                warm_up.run(&wikis).await;
+                            }
+                            Err(error) => warn!("warm-up: could not load {seed}: {error}"),
+                        }
+                        tokio::time::sleep(period).await;
+                    }
+                });
+            })
+        }))
         .register("/", catchers![not_found])
         .mount("/m", mirror_routes)
+        .mount("/m", export::export_routes())
+        .mount("/api/v1", api::api_routes())
+        .mount("/", image_proxy::image_proxy_routes())
         .mount(
             "/",
             routes![
@@ -517,18 +1054,224 @@ fn rocket() -> Rocket<Build> {
                 base_sitemap_xml,
                 generated_sitemap_xml,
                 seed_sitemaps,
+                wiki_sitemap_index,
                 wiki_debug_sitemaps,
+                global_search,
                 versionz,
                 healthz,
             ],
         )
-        .manage(
-            Client::builder()
-                .user_agent(APP_USER_AGENT)
-                .timeout(Duration::from_secs(10))
-                .connect_timeout(Duration::from_secs(3))
-                .redirect(reqwest::redirect::Policy::none())
-                .build()
-                .expect("Could not build client"),
-        )
+        .manage(http_client())
+}
+
+fn http_client() -> Client {
+    Client::builder()
+        .user_agent(APP_USER_AGENT)
+        .timeout(Duration::from_secs(10))
+        .connect_timeout(Duration::from_secs(3))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("Could not build client")
+}
+
+#[derive(Parser)]
+#[command(version, about = "Mirror GitHub wikis for search engines")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the web server (the default).
+    Serve,
+    /// Save every page of a wiki as static HTML.
+    Export {
+        account: String,
+        repository: String,
+        /// Directory to write the pages to, or a `.zip`, single page `.html` or `.epub` file.
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Fetch every page of the wikis in a seed list once, at the configured rate.
+    WarmUp {
+        /// File or URL listing the wikis, one `account/repository` or wiki URL per line.
+        seed: String,
+        /// Directory to save each wiki's sitemap in.
+        #[arg(long)]
+        sitemaps: Option<PathBuf>,
+    },
+    /// Render a cloned `<repository>.wiki` directory to HTML files, as the mirror would.
+    Render {
+        dir: PathBuf,
+        /// Directory to write the pages to.
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Account the wiki belongs to, for links to GitHub.
+        #[arg(long, default_value = "local")]
+        account: String,
+        /// Repository the wiki belongs to. Defaults to the directory name without `.wiki`.
+        #[arg(long)]
+        repository: Option<String>,
+    },
+}
+
+async fn export_command(account: &str, repository: &str, output: &Path) -> Result<(), String> {
+    // Same settings as the server, from `Rocket.toml` and `ROCKET_*` variables.
+    let config: Config = rocket::Config::figment()
+        .extract()
+        .map_err(|error| format!("Invalid configuration: {error}"))?;
+
+    let client = http_client();
+    let options = config.render_options();
+    let format = output
+        .extension()
+        .and_then(|extension| export::ExportFormat::from_extension(&extension.to_string_lossy()));
+
+    let failed =
+        |error: &dyn std::fmt::Display| format!("Could not export {account}/{repository}: {error}");
+    let sources = export::retrieve_wiki_sources(account, repository, &client)
+        .await
+        .map_err(|error| failed(&error))?;
+
+    let written = match format {
+        Some(format) => {
+            let file = export::export_file(format, &sources, &options, config.base_url())
+                .map_err(|error| failed(&error))?;
+            std::fs::write(output, file)
+        }
+        None => {
+            let files = export::export_wiki(&sources, &options, config.base_url())
+                .map_err(|error| failed(&error))?;
+            export::write_directory(&files, output)
+        }
+    };
+    written.map_err(|error| format!("Could not write {}: {error}", output.display()))?;
+
+    println!("Exported {account}/{repository} to {}", output.display());
+    Ok(())
+}
+
+fn render_command(
+    dir: &Path,
+    output: &Path,
+    account: &str,
+    repository: Option<String>,
+) -> Result<(), String> {
+    let config: Config = rocket::Config::figment()
+        .extract()
+        .map_err(|error| format!("Invalid configuration: {error}"))?;
+    let repository = repository.unwrap_or_else(|| {
+        let name = dir
+            .canonicalize()
+            .ok()
+            .and_then(|dir| {
+                dir.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_default();
+        name.trim_end_matches(".wiki").to_string()
+    });
+    let options = RenderOptions {
+        // Pages are previewed locally, not from a deployment with the proxy.
+        proxy_images: false,
+        ..config.render_options()
+    };
+
+    let pages = local_wiki::render_wiki_directory(
+        dir,
+        output,
+        account,
+        &repository,
+        &options,
+        config.base_url(),
+    )
+    .map_err(|error| format!("Could not render {}: {error}", dir.display()))?;
+
+    println!(
+        "Rendered {pages} pages to {}",
+        output.join("Home.html").display()
+    );
+    Ok(())
+}
+
+async fn warm_up_command(seed: &str, sitemaps: Option<&Path>) -> Result<(), String> {
+    // Building Rocket sets up its logger, which reports the progress.
+    let config: Config = rocket::build()
+        .figment()
+        .extract()
+        .map_err(|error| format!("Invalid configuration: {error}"))?;
+    let client = http_client();
+    let wikis = warm_up::load_seed(seed, &client)
+        .await
+        .map_err(|error| format!("Could not load {seed}: {error}"))?;
+    // Only a persisted index outlives this run. It is locked while the server is using it.
+    let search = match &config.search_index_path {
+        Some(path) => match SearchIndex::open(Some(path)) {
+            Ok(search) => Some(Arc::new(search)),
+            Err(error) => {
+                warn!("warm-up: not indexing pages: {error}");
+                None
+            }
+        },
+        None => None,
+    };
+    // Only so many pages wait for a commit, so commit as the run goes.
+    if let Some(search) = &search {
+        spawn_search_commits(search.clone());
+    }
+
+    let stats = warm_up::WarmUp {
+        client: &client,
+        config: &config,
+        search: search.as_deref(),
+        sitemap_dir: sitemaps,
+    }
+    .run(&wikis)
+    .await;
+    if let Some(search) = &search {
+        search
+            .commit()
+            .map_err(|error| format!("Could not save search index: {error}"))?;
+    }
+
+    println!(
+        "Warmed up {} of {} wikis, {} pages, {} failures",
+        stats.wikis,
+        wikis.len(),
+        stats.pages,
+        stats.failures
+    );
+    Ok(())
+}
+
+#[rocket::main]
+async fn main() -> ExitCode {
+    let result = match Cli::parse().command.unwrap_or(Command::Serve) {
+        Command::Serve => rocket()
+            .launch()
+            .await
+            .map(|_| ())
+            .map_err(|error| error.to_string()),
+        Command::Export {
+            account,
+            repository,
+            output,
+        } => export_command(&account, &repository, &output).await,
+        Command::WarmUp { seed, sitemaps } => warm_up_command(&seed, sitemaps.as_deref()).await,
+        Command::Render {
+            dir,
+            output,
+            account,
+            repository,
+        } => render_command(&dir, &output, &account, repository),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
 }
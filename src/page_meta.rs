@@ -0,0 +1,208 @@
+use std::sync::LazyLock;
+
+use rocket::serde::json::{json, Value};
+use rocket::serde::Deserialize;
+use scraper::{Html, Selector};
+
+static PARAGRAPH_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse("p").expect("paragraph selector should compile"));
+
+const MAX_DESCRIPTION_CHARS: usize = 160;
+
+/// Which URL mirror pages declare as canonical.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum CanonicalStrategy {
+    /// No `<link rel="canonical">`.
+    #[default]
+    None,
+    /// The page on this mirror.
+    Mirror,
+    /// The page on github.com.
+    Original,
+}
+
+/// Search engine and social metadata for a mirrored page.
+pub struct PageMeta {
+    pub description: String,
+    /// Absolute URL of the page on this mirror.
+    pub url: String,
+    pub canonical_url: Option<String>,
+    /// Schema.org JSON-LD, safe to place inside `<script>`.
+    pub json_ld: String,
+}
+
+/// Details about a mirrored page needed to describe it.
+pub struct PageInfo<'a> {
+    pub account: &'a str,
+    pub repository: &'a str,
+    pub page_name: &'a str,
+    pub title: &'a str,
+    pub mirror_home_url: &'a str,
+    pub mirror_url: &'a str,
+    pub original_url: &'a str,
+    /// W3C datetime of the last edit, if known.
+    pub date_modified: Option<&'a str>,
+}
+
+/// Plain text of the first non-empty paragraph, cut at a word boundary.
+pub fn describe(html: &str) -> String {
+    let fragment = Html::parse_fragment(html);
+    let text = fragment
+        .select(&PARAGRAPH_SELECTOR)
+        .map(|paragraph| {
+            paragraph
+                .text()
+                .flat_map(str::split_whitespace)
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .find(|text| !text.is_empty())
+        .unwrap_or_default();
+
+    if text.chars().count() <= MAX_DESCRIPTION_CHARS {
+        return text;
+    }
+    let cut: String = text.chars().take(MAX_DESCRIPTION_CHARS).collect();
+    let cut = match cut.rsplit_once(' ') {
+        Some((words, _)) if !words.is_empty() => words,
+        _ => &cut,
+    };
+    format!(
+        "{}…",
+        cut.trim_end_matches(|c: char| c.is_ascii_punctuation())
+    )
+}
+
+fn breadcrumbs(info: &PageInfo) -> Value {
+    let crumbs = [
+        (
+            info.account.to_string(),
+            format!("https://github.com/{}", info.account),
+        ),
+        (
+            info.repository.to_string(),
+            format!("https://github.com/{}/{}", info.account, info.repository),
+        ),
+        ("Wiki".to_string(), info.mirror_home_url.to_string()),
+        (info.page_name.to_string(), info.mirror_url.to_string()),
+    ];
+    let items: Vec<Value> = crumbs
+        .into_iter()
+        .enumerate()
+        .map(|(index, (name, item))| {
+            json!({
+                "@type": "ListItem",
+                "position": index + 1,
+                "name": name,
+                "item": item,
+            })
+        })
+        .collect();
+
+    json!({
+        "@context": "https://schema.org",
+        "@type": "BreadcrumbList",
+        "itemListElement": items,
+    })
+}
+
+fn tech_article(info: &PageInfo, description: &str) -> Value {
+    let mut article = json!({
+        "@context": "https://schema.org",
+        "@type": "TechArticle",
+        "headline": info.title,
+        "description": description,
+        "url": info.mirror_url,
+        "isBasedOn": info.original_url,
+    });
+    if let Some(date_modified) = info.date_modified {
+        article["dateModified"] = json!(date_modified);
+    }
+    article
+}
+
+impl PageMeta {
+    pub fn new(info: &PageInfo, html: &str, canonical: CanonicalStrategy) -> Self {
+        let description = describe(html);
+        let json_ld = json!([breadcrumbs(info), tech_article(info, &description)])
+            .to_string()
+            // Keep `</script>` and `<!--` in page text from ending the script element.
+            .replace('<', "\\u003c");
+
+        Self {
+            canonical_url: match canonical {
+                CanonicalStrategy::None => None,
+                CanonicalStrategy::Mirror => Some(info.mirror_url.to_string()),
+                CanonicalStrategy::Original => Some(info.original_url.to_string()),
+            },
+            url: info.mirror_url.to_string(),
+            description,
+            json_ld,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> PageInfo<'static> {
+        PageInfo {
+            account: "a",
+            repository: "r",
+            page_name: "Getting Started",
+            title: "Getting Started - a/r GitHub Wiki",
+            mirror_home_url: "https://example.com/m/a/r/wiki",
+            mirror_url: "https://example.com/m/a/r/wiki/Getting-Started",
+            original_url: "https://github.com/a/r/wiki/Getting-Started",
+            date_modified: Some("2023-01-01T00:00:00Z"),
+        }
+    }
+
+    #[test]
+    fn descriptions() {
+        assert_eq!(
+            describe("<h1>Title</h1><p>\n</p><p>First <em>real</em>\nparagraph.</p><p>Second.</p>"),
+            "First real paragraph."
+        );
+        assert_eq!(describe("<ul><li>no paragraphs</li></ul>"), "");
+
+        let long = format!("<p>{}</p>", "word, ".repeat(40));
+        let description = describe(&long);
+        assert!(description.chars().count() <= MAX_DESCRIPTION_CHARS + 1);
+        assert!(description.ends_with("word…"));
+    }
+
+    #[test]
+    fn json_ld_and_canonical() {
+        let meta = PageMeta::new(
+            &info(),
+            "<p>Install with &lt;/script&gt;&lt;script&gt;alert(1)</p>",
+            CanonicalStrategy::Original,
+        );
+
+        assert_eq!(
+            meta.canonical_url.as_deref(),
+            Some("https://github.com/a/r/wiki/Getting-Started")
+        );
+        assert!(!meta.json_ld.contains('<'));
+        let json_ld: Value = rocket::serde::json::from_str(&meta.json_ld).unwrap();
+        assert_eq!(json_ld[0]["@type"], "BreadcrumbList");
+        assert_eq!(json_ld[0]["itemListElement"][3]["position"], 4);
+        assert_eq!(json_ld[0]["itemListElement"][3]["name"], "Getting Started");
+        assert_eq!(json_ld[1]["@type"], "TechArticle");
+        assert_eq!(json_ld[1]["dateModified"], "2023-01-01T00:00:00Z");
+
+        let meta = PageMeta::new(
+            &PageInfo {
+                date_modified: None,
+                ..info()
+            },
+            "",
+            CanonicalStrategy::None,
+        );
+        assert_eq!(meta.canonical_url, None);
+        assert!(!meta.json_ld.contains("dateModified"));
+    }
+}
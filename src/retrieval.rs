@@ -1,15 +1,21 @@
-use quick_xml::events::BytesText;
 use reqwest::{Client, StatusCode};
 use scraper::{Html, Selector};
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::sync::LazyLock;
+use std::time::Duration;
 use thiserror::Error;
 
+use crate::cache::TtlCache;
 use crate::decommission::DECOMMISSION_LIST;
-use crate::scraper::process_html_index;
+use crate::scraper::{
+    process_html_index, process_html_index_last_updated, process_html_index_next_page,
+};
+use crate::sitemap::{write_urlset, SitemapUrl};
+use crate::wiki_page::{sort_and_dedup, WikiPageRef};
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Content {
     AsciiDoc(String),
     Creole(String),
@@ -23,6 +29,40 @@ pub enum Content {
     FallbackHtml(String),
 }
 
+impl Content {
+    /// Name of the markup language the page was written in.
+    pub fn format(&self) -> &'static str {
+        match self {
+            Content::AsciiDoc(_) => "asciidoc",
+            Content::Creole(_) => "creole",
+            Content::Markdown(_) => "markdown",
+            Content::Mediawiki(_) => "mediawiki",
+            Content::Orgmode(_) => "org",
+            Content::Pod(_) => "pod",
+            Content::Rdoc(_) => "rdoc",
+            Content::Textile(_) => "textile",
+            Content::ReStructuredText(_) => "rst",
+            Content::FallbackHtml(_) => "github_html",
+        }
+    }
+
+    /// The page source, or GitHub's rendered HTML for the fallback.
+    pub fn source(&self) -> &str {
+        match self {
+            Content::AsciiDoc(source)
+            | Content::Creole(source)
+            | Content::Markdown(source)
+            | Content::Mediawiki(source)
+            | Content::Orgmode(source)
+            | Content::Pod(source)
+            | Content::Rdoc(source)
+            | Content::Textile(source)
+            | Content::ReStructuredText(source)
+            | Content::FallbackHtml(source) => source,
+        }
+    }
+}
+
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum ContentError {
     #[error("not found")]
@@ -36,16 +76,19 @@ pub enum ContentError {
 }
 
 const FALLBACK_HOST: &str = "https://gh-mirror-gucl6ahvva-uc.a.run.app";
-static HTML_IN_MARKDOWN_RE: LazyLock<regex::Regex> =
-    LazyLock::new(|| regex::Regex::new("<.{3,10}>").expect("html detection regex should compile"));
 static WIKI_BODY_SELECTOR: LazyLock<Selector> =
     LazyLock::new(|| Selector::parse("#wiki-body").expect("wiki body selector should compile"));
+static HEADER_TITLE_SELECTOR: LazyLock<Selector> = LazyLock::new(|| {
+    Selector::parse("h1.gh-header-title").expect("header title selector should compile")
+});
+static TITLE_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse("title").expect("title selector should compile"));
 
 fn repo_slug(account: &str, repository: &str) -> String {
     format!("{account}/{repository}")
 }
 
-fn raw_wiki_source_url(account: &str, repository: &str, page: &str, extension: &str) -> String {
+pub fn raw_wiki_source_url(account: &str, repository: &str, page: &str, extension: &str) -> String {
     let page_encoded =
         percent_encoding::utf8_percent_encode(page, percent_encoding::NON_ALPHANUMERIC);
     format!(
@@ -72,8 +115,12 @@ fn response_to_content_error(status: StatusCode) -> Result<(), ContentError> {
     }
 }
 
-fn markdown_contains_html(content: &Content) -> bool {
-    matches!(content, Content::Markdown(md) if HTML_IN_MARKDOWN_RE.is_match(md))
+// Skip decommissioned wikis
+fn ensure_not_decommissioned(account: &str, repository: &str) -> Result<(), ContentError> {
+    if DECOMMISSION_LIST.contains(repo_slug(account, repository).as_str()) {
+        return Err(ContentError::Decommissioned);
+    }
+    Ok(())
 }
 
 async fn with_rate_limit_fallback<T, Fut, F>(fetch: F) -> Result<T, ContentError>
@@ -93,17 +140,33 @@ pub async fn retrieve_source_file(
     page: &str,
     client: &Client,
 ) -> Result<Content, ContentError> {
-    // Skip decommissioned wikis
-    if DECOMMISSION_LIST.contains(repo_slug(account, repository).as_str()) {
-        return Err(ContentError::Decommissioned);
-    }
+    retrieve_source_file_paced(account, repository, page, client, || async {}).await
+}
+
+/// [`retrieve_source_file`], awaiting `pace` before each request to GitHub.
+pub async fn retrieve_source_file_paced<P, Wait>(
+    account: &str,
+    repository: &str,
+    page: &str,
+    client: &Client,
+    pace: P,
+) -> Result<Content, ContentError>
+where
+    P: Fn() -> Wait,
+    Wait: Future<Output = ()>,
+{
+    ensure_not_decommissioned(account, repository)?;
+    let pace = &pace;
 
+    // GitHub's rendering is only needed when there is no Markdown source to render ourselves.
+    pace().await;
     match retrieve_source_file_extension(account, repository, page, client, Content::Markdown, "md")
         .await
     {
-        Ok(content) if !markdown_contains_html(&content) => Ok(content),
-        Ok(_) | Err(_) => {
+        Ok(content) => Ok(content),
+        Err(_) => {
             with_rate_limit_fallback(|domain| async move {
+                pace().await;
                 retrieve_fallback_html(account, repository, page, client, domain).await
             })
             .await
@@ -118,8 +181,12 @@ async fn retrieve_github_com_html(
     client: &Client,
     domain: &str,
 ) -> Result<String, ContentError> {
+    retrieve_github_com_url(&wiki_html_url(domain, account, repository, page), client).await
+}
+
+async fn retrieve_github_com_url(url: &str, client: &Client) -> Result<String, ContentError> {
     let response = client
-        .get(wiki_html_url(domain, account, repository, page))
+        .get(url)
         .send()
         .await
         .map_err(|error| ContentError::OtherError(error.to_string()))?;
@@ -142,6 +209,9 @@ async fn retrieve_fallback_html(
     let html = retrieve_github_com_html(account, repository, page, client, domain).await?;
 
     let document = Html::parse_document(&html);
+    if let Some(title) = fallback_page_title(&document) {
+        remember_page_title(account, repository, page, title);
+    }
     document
         .select(&WIKI_BODY_SELECTOR)
         .next()
@@ -150,8 +220,77 @@ async fn retrieve_fallback_html(
         .ok_or(ContentError::NotFound)
 }
 
+// GitHub's page header, or `Page · account/repository Wiki · GitHub`.
+fn fallback_page_title(document: &Html) -> Option<String> {
+    let header = document
+        .select(&HEADER_TITLE_SELECTOR)
+        .next()
+        .map(|header| header.text().collect::<String>());
+    let title = header.or_else(|| {
+        let title = document
+            .select(&TITLE_SELECTOR)
+            .next()?
+            .text()
+            .collect::<String>();
+        title.split(" · ").next().map(str::to_string)
+    })?;
+    let title = title.trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+// Titles as GitHub shows them, from page listings and GitHub's rendered pages.
+static PAGE_TITLES: LazyLock<TtlCache<(String, String, String), String>> =
+    LazyLock::new(|| TtlCache::new(Duration::from_secs(24 * 60 * 60), 100_000));
+
+fn page_key(account: &str, repository: &str, page: &str) -> (String, String, String) {
+    (
+        account.to_string(),
+        repository.to_string(),
+        page.to_string(),
+    )
+}
+
+fn remember_page_title(account: &str, repository: &str, page: &str, title: String) {
+    PAGE_TITLES.insert(page_key(account, repository, page), title);
+}
+
+/// The page's real title, if it has been seen in a page listing or on GitHub.
+pub fn known_page_title(account: &str, repository: &str, page: &str) -> Option<String> {
+    PAGE_TITLES.get(&page_key(account, repository, page))
+}
+
+pub type ContentConstructor = fn(String) -> Content;
+
+/// Page source file extensions GitHub wikis accept, with the markup they hold.
+pub const SOURCE_EXTENSIONS: &[(&str, ContentConstructor)] = &[
+    ("md", Content::Markdown),
+    ("markdown", Content::Markdown),
+    ("mdown", Content::Markdown),
+    ("mkdn", Content::Markdown),
+    ("asciidoc", Content::AsciiDoc),
+    ("adoc", Content::AsciiDoc),
+    ("creole", Content::Creole),
+    ("mediawiki", Content::Mediawiki),
+    ("wiki", Content::Mediawiki),
+    ("org", Content::Orgmode),
+    ("pod", Content::Pod),
+    ("rdoc", Content::Rdoc),
+    ("textile", Content::Textile),
+    ("rst", Content::ReStructuredText),
+];
+
+pub fn content_constructor_for_extension(extension: &str) -> Option<ContentConstructor> {
+    SOURCE_EXTENSIONS
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map(|(_, constructor)| *constructor)
+}
+
+static SOURCE_FILE_CACHE: LazyLock<TtlCache<(String, String, String, String), String>> =
+    LazyLock::new(|| TtlCache::new(Duration::from_secs(10 * 60), 2048));
+
 // https://github-wiki-see.page/m/nelsonjchen/github-wiki-test/wiki/Fallback
-async fn retrieve_source_file_extension<T>(
+pub async fn retrieve_source_file_extension<T>(
     account: &str,
     repository: &str,
     page: &str,
@@ -162,6 +301,18 @@ async fn retrieve_source_file_extension<T>(
 where
     T: Fn(String) -> Content,
 {
+    ensure_not_decommissioned(account, repository)?;
+
+    let cache_key = (
+        account.to_string(),
+        repository.to_string(),
+        page.to_string(),
+        extension.to_string(),
+    );
+    if let Some(body) = SOURCE_FILE_CACHE.get(&cache_key) {
+        return Ok(enum_constructor(body));
+    }
+
     let response = client
         .get(raw_wiki_source_url(account, repository, page, extension))
         .send()
@@ -175,107 +326,267 @@ where
         .await
         .map_err(|error| ContentError::OtherError(error.to_string()))?;
 
+    SOURCE_FILE_CACHE.insert(cache_key, body.clone());
     Ok(enum_constructor(body))
 }
 
-pub async fn retrieve_wiki_index(
+// Upper bound on how many `_pages` listing pages are followed for one wiki.
+const MAX_PAGE_LISTING_PAGES: usize = 50;
+
+#[derive(Debug, Default, Clone)]
+pub struct WikiPageListing {
+    /// Every page of the wiki, Home first and then by title.
+    pub pages: Vec<WikiPageRef>,
+    /// Page slug to the W3C datetime it was last updated.
+    pub last_updated: HashMap<String, String>,
+}
+
+// The sidebar on wiki pages is truncated, so walk the full `/wiki/_pages` listing instead.
+async fn collect_page_listing<F, Fut>(
+    account: &str,
+    repository: &str,
+    fetch: F,
+) -> Result<WikiPageListing, ContentError>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<String, ContentError>>,
+{
+    let mut listing = WikiPageListing::default();
+    let mut visited = HashSet::new();
+    let mut next_path = Some(format!("/{account}/{repository}/wiki/_pages"));
+
+    while let Some(path) = next_path.take() {
+        if visited.len() >= MAX_PAGE_LISTING_PAGES || !visited.insert(path.clone()) {
+            break;
+        }
+        let html = fetch(path).await?;
+        listing
+            .pages
+            .extend(process_html_index(&html, account, repository));
+        listing.last_updated.extend(
+            process_html_index_last_updated(&html)
+                .into_iter()
+                .map(|(page, datetime)| (page.slug, datetime)),
+        );
+        next_path = process_html_index_next_page(&html);
+    }
+
+    listing.pages = sort_and_dedup(listing.pages);
+    Ok(listing)
+}
+
+pub async fn retrieve_wiki_page_listing(
     account: &str,
     repository: &str,
     client: &Client,
-) -> Result<Content, ContentError> {
-    let html = with_rate_limit_fallback(|domain| async move {
-        retrieve_github_com_html(account, repository, "", client, domain).await
+) -> Result<WikiPageListing, ContentError> {
+    retrieve_wiki_page_listing_paced(account, repository, client, || async {}).await
+}
+
+/// [`retrieve_wiki_page_listing`], awaiting `pace` before each request to GitHub.
+pub async fn retrieve_wiki_page_listing_paced<P, Wait>(
+    account: &str,
+    repository: &str,
+    client: &Client,
+    pace: P,
+) -> Result<WikiPageListing, ContentError>
+where
+    P: Fn() -> Wait,
+    Wait: Future<Output = ()>,
+{
+    if let Some(listing) = cached_wiki_page_listing(account, repository) {
+        return Ok(listing);
+    }
+    let pace = &pace;
+
+    let listing = collect_page_listing(account, repository, |path| async move {
+        with_rate_limit_fallback(|domain| {
+            let url = format!("{domain}{path}");
+            async move {
+                pace().await;
+                retrieve_github_com_url(&url, client).await
+            }
+        })
+        .await
     })
     .await?;
-    let wiki_page_urls = process_html_index(&html);
-    let content = Content::Markdown(format!(
+
+    for page in &listing.pages {
+        let slug = percent_encoding::percent_decode_str(&page.slug).decode_utf8_lossy();
+        remember_page_title(account, repository, &slug, page.title.clone());
+        if let Some(last_updated) = listing.last_updated.get(&page.slug) {
+            PAGE_LAST_UPDATED.insert(page_key(account, repository, &slug), last_updated.clone());
+        }
+    }
+    WIKI_LISTING_CACHE.insert(
+        (account.to_string(), repository.to_string()),
+        listing.clone(),
+    );
+    Ok(listing)
+}
+
+static WIKI_LISTING_CACHE: LazyLock<TtlCache<(String, String), WikiPageListing>> =
+    LazyLock::new(|| TtlCache::new(Duration::from_secs(60 * 60), 1024));
+
+/// The wiki's page listing if it has been fetched recently.
+pub fn cached_wiki_page_listing(account: &str, repository: &str) -> Option<WikiPageListing> {
+    WIKI_LISTING_CACHE.get(&(account.to_string(), repository.to_string()))
+}
+
+// Wikis whose listing a page view started fetching lately, so that views of a wiki with a
+// slow listing don't each start another fetch.
+static LISTING_FETCHES: LazyLock<TtlCache<(String, String), ()>> =
+    LazyLock::new(|| TtlCache::new(Duration::from_secs(60), 1024));
+
+/// Await `fetch` for up to `timeout`, leaving it running in the background past that.
+async fn finished_within<T: Send + 'static>(
+    timeout: Duration,
+    fetch: impl Future<Output = Option<T>> + Send + 'static,
+) -> Option<T> {
+    let fetch = tokio::spawn(fetch);
+    tokio::time::timeout(timeout, fetch).await.ok()?.ok()?
+}
+
+/// The wiki's page listing, if it is cached or can be fetched within `timeout`.
+///
+/// A slower fetch carries on and caches the listing for later views.
+pub async fn wiki_page_listing_within(
+    account: &str,
+    repository: &str,
+    client: &Client,
+    timeout: Duration,
+) -> Option<WikiPageListing> {
+    if let Some(listing) = cached_wiki_page_listing(account, repository) {
+        return Some(listing);
+    }
+    let key = (account.to_string(), repository.to_string());
+    if LISTING_FETCHES.get(&key).is_some() {
+        return None;
+    }
+    LISTING_FETCHES.insert(key.clone(), ());
+
+    let client = client.clone();
+    finished_within(timeout, async move {
+        let (account, repository) = key;
+        retrieve_wiki_page_listing(&account, &repository, &client)
+            .await
+            .ok()
+    })
+    .await
+}
+
+// Filled in whenever a wiki's page listing is fetched, e.g. for its sitemap.
+static PAGE_LAST_UPDATED: LazyLock<TtlCache<(String, String, String), String>> =
+    LazyLock::new(|| TtlCache::new(Duration::from_secs(24 * 60 * 60), 100_000));
+
+/// When a page was last edited, if a recent page listing said so.
+pub fn known_last_updated(account: &str, repository: &str, page: &str) -> Option<String> {
+    PAGE_LAST_UPDATED.get(&page_key(account, repository, page))
+}
+
+pub async fn retrieve_wiki_index(
+    account: &str,
+    repository: &str,
+    client: &Client,
+) -> Result<Content, ContentError> {
+    let pages = retrieve_wiki_page_listing(account, repository, client)
+        .await?
+        .pages;
+    Ok(wiki_index_content(&pages))
+}
+
+/// Markdown list of links to every page, for the page index.
+pub fn wiki_index_content(pages: &[WikiPageRef]) -> Content {
+    Content::Markdown(format!(
         "{} page(s) in this GitHub Wiki:
 
 {}
 ",
-        wiki_page_urls.len(),
-        wiki_page_urls
-            .into_iter()
-            .map(|(url, text)| format!("* [{text}]({url})"))
+        pages.len(),
+        pages
+            .iter()
+            .map(|page| {
+                format!(
+                    "* [{}](<{}>)",
+                    page.title.replace('[', "\\[").replace(']', "\\]"),
+                    page.github_path()
+                )
+            })
             .collect::<Vec<String>>()
             .join("\n"),
-    ));
-    Ok(content)
+    ))
+}
+
+static WIKI_SITEMAP_CACHE: LazyLock<TtlCache<(String, String), String>> =
+    LazyLock::new(|| TtlCache::new(Duration::from_secs(60 * 60), 4096));
+// Wikis that have been mirrored recently, for the self-hosted sitemap index.
+static KNOWN_WIKIS: LazyLock<TtlCache<(String, String), ()>> =
+    LazyLock::new(|| TtlCache::new(Duration::from_secs(7 * 24 * 60 * 60), 50_000));
+
+pub fn remember_wiki(account: &str, repository: &str) {
+    KNOWN_WIKIS.insert((account.to_string(), repository.to_string()), ());
+}
+
+pub fn known_wikis() -> Vec<(String, String)> {
+    let mut wikis = KNOWN_WIKIS.keys();
+    wikis.sort();
+    wikis
 }
 
 pub async fn retrieve_wiki_sitemap_index(
     account: &str,
     repository: &str,
     client: &Client,
+    base_url: &str,
 ) -> Result<String, ContentError> {
-    let html = with_rate_limit_fallback(|domain| async move {
-        retrieve_github_com_html(account, repository, "", client, domain).await
-    })
-    .await?;
-    let mut wiki_page_urls = process_html_index(&html);
-
-    // Add the synthetic index page
-    wiki_page_urls.push((
-        format!("/{account}/{repository}/wiki_index"),
-        "Wiki Index".to_string(),
-    ));
-
-    use quick_xml::events::{BytesEnd, BytesStart, Event};
-
-    use quick_xml::Writer;
-    use std::io::Cursor;
-
-    let mut writer = Writer::new(Cursor::new(Vec::new()));
-
-    let mut urlset_el = BytesStart::new("urlset");
-    urlset_el.push_attribute(("xmlns", "http://www.sitemaps.org/schemas/sitemap/0.9"));
-    urlset_el.push_attribute(("xmlns:xsi", "http://www.w3.org/2001/XMLSchema-instance"));
-    urlset_el.push_attribute(("xsi:schemaLocation", "http://www.sitemaps.org/schemas/sitemap/0.9 http://www.sitemaps.org/schemas/sitemap/0.9/sitemap.xsd"));
-
-    writer
-        .write_event(Event::Start(urlset_el))
-        .map_err(|o| ContentError::OtherError(o.to_string()))?;
-
-    for (url, _) in wiki_page_urls {
-        let url_el = BytesStart::new("url");
-        writer
-            .write_event(Event::Start(url_el))
-            .map_err(|o| ContentError::OtherError(o.to_string()))?;
-
-        let loc_el = BytesStart::new("loc");
-        writer
-            .write_event(Event::Start(loc_el))
-            .map_err(|o| ContentError::OtherError(o.to_string()))?;
-
-        writer
-            .write_event(Event::Text(BytesText::new(&format!(
-                "https://github-wiki-see.page/m{url}"
-            ))))
-            .map_err(|o| ContentError::OtherError(o.to_string()))?;
-
-        writer
-            .write_event(Event::End(BytesEnd::new("loc")))
-            .map_err(|o| ContentError::OtherError(o.to_string()))?;
-
-        writer
-            .write_event(Event::End(BytesEnd::new("url")))
-            .map_err(|o| ContentError::OtherError(o.to_string()))?;
+    let cache_key = (account.to_string(), repository.to_string());
+    if let Some(xml) = WIKI_SITEMAP_CACHE.get(&cache_key) {
+        return Ok(xml);
     }
 
-    writer
-        .write_event(Event::End(BytesEnd::new("urlset")))
-        .map_err(|op| ContentError::OtherError(op.to_string()))?;
+    let listing = retrieve_wiki_page_listing(account, repository, client).await?;
+
+    let mut urls: Vec<SitemapUrl> = listing
+        .pages
+        .iter()
+        .map(|page| SitemapUrl {
+            loc: format!("{base_url}{}", page.mirror_path()),
+            lastmod: listing.last_updated.get(&page.slug).cloned(),
+        })
+        .collect();
 
-    use std::str;
-    let written = &writer.into_inner().into_inner();
-    let xml_str = str::from_utf8(written).map_err(|op| ContentError::OtherError(op.to_string()))?;
-    Ok(xml_str.to_string())
+    // Add the synthetic index page
+    urls.push(SitemapUrl {
+        loc: format!("{base_url}/m/{account}/{repository}/wiki_index"),
+        lastmod: None,
+    });
+
+    let xml = write_urlset(&urls)?;
+    WIKI_SITEMAP_CACHE.insert(cache_key, xml.clone());
+    remember_wiki(account, repository);
+    Ok(xml)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn slow_fetches_carry_on() {
+        assert_eq!(
+            finished_within(Duration::from_secs(5), async { Some(1) }).await,
+            Some(1)
+        );
+
+        let (done, finished) = tokio::sync::oneshot::channel();
+        let slow = async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            done.send(()).ok()
+        };
+        assert_eq!(finished_within(Duration::from_millis(1), slow).await, None);
+        assert_eq!(finished.await, Ok(()));
+    }
+
     #[tokio::test]
     async fn basic() {
         let client = Client::new();
@@ -312,6 +623,23 @@ mod tests {
         assert!(content.is_ok());
     }
 
+    #[test]
+    fn fallback_titles() {
+        let document = Html::parse_document(include_str!("../test-data/wiki-index.html"));
+        assert_eq!(fallback_page_title(&document).as_deref(), Some("Home"));
+
+        let document = Html::parse_document(
+            "<html><head><title>How to bundle? · naver/billboard.js Wiki · GitHub</title></head></html>",
+        );
+        assert_eq!(
+            fallback_page_title(&document).as_deref(),
+            Some("How to bundle?")
+        );
+
+        let document = Html::parse_document("<p>No title</p>");
+        assert_eq!(fallback_page_title(&document), None);
+    }
+
     #[tokio::test]
     async fn html_in_markdown() {
         let client = Client::new();
@@ -319,9 +647,8 @@ mod tests {
         let future = retrieve_source_file("wlsdn2316", "1-tetris-", "Functions", &client);
         let content = future.await;
 
-        assert!(content.is_ok());
-        // Fallback must be used for HTML in Markdown documents
-        assert!(matches!(content, Ok(Content::FallbackHtml(_))));
+        // HTML in Markdown is rendered from source and sanitized, not fetched from GitHub
+        assert!(matches!(content, Ok(Content::Markdown(_))));
     }
 
     #[tokio::test]
@@ -358,6 +685,63 @@ mod tests {
         assert!(content.is_ok());
     }
 
+    #[tokio::test]
+    async fn source_file_extension_decommissioned() {
+        let client = Client::new();
+
+        let content = retrieve_source_file_extension(
+            "koalaman",
+            "shellcheck",
+            "Home",
+            &client,
+            &Content::Markdown,
+            "md",
+        )
+        .await;
+
+        assert_eq!(content.unwrap_err(), ContentError::Decommissioned);
+    }
+
+    #[test]
+    fn source_extensions() {
+        assert!(matches!(
+            content_constructor_for_extension("md").map(|constructor| constructor(String::new())),
+            Some(Content::Markdown(_))
+        ));
+        assert!(matches!(
+            content_constructor_for_extension("rst").map(|constructor| constructor(String::new())),
+            Some(Content::ReStructuredText(_))
+        ));
+        assert!(content_constructor_for_extension("html").is_none());
+    }
+
+    #[tokio::test]
+    async fn page_listing_follows_pagination() {
+        let listing =
+            collect_page_listing("nelsonjchen", "github-wiki-test-large", |path| async move {
+                match path.as_str() {
+                    "/nelsonjchen/github-wiki-test-large/wiki/_pages" => {
+                        Ok(include_str!("../test-data/wiki-pages-large.html").to_string())
+                    }
+                    "/nelsonjchen/github-wiki-test-large/wiki/_pages?page=2" => {
+                        Ok(include_str!("../test-data/wiki-pages-large-2.html").to_string())
+                    }
+                    _ => Err(ContentError::NotFound),
+                }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(listing.pages.len(), 450);
+        assert_eq!(listing.last_updated.len(), 450);
+        assert_eq!(listing.pages.first().unwrap().slug, "Backup-API");
+        assert_eq!(listing.pages.last().unwrap().slug, "Troubleshoot-Windows");
+        assert_eq!(
+            listing.last_updated.get("Backup-API").map(String::as_str),
+            Some("2023-01-01T00:00:00Z")
+        );
+    }
+
     #[tokio::test]
     async fn page_list() {
         let client = Client::new();
@@ -371,7 +755,12 @@ mod tests {
     #[tokio::test]
     async fn wiki_sitemap_index() {
         let client = Client::new();
-        let future = retrieve_wiki_sitemap_index("nelsonjchen", "github-wiki-test", &client);
+        let future = retrieve_wiki_sitemap_index(
+            "nelsonjchen",
+            "github-wiki-test",
+            &client,
+            "https://github-wiki-see.page",
+        );
         let content = future.await;
 
         println!("{content:?}");
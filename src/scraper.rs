@@ -1,24 +1,116 @@
-use comrak::{markdown_to_html, Options};
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+use ammonia::Builder;
+use comrak::nodes::NodeValue;
+use comrak::{format_html, parse_document, Arena, Options};
+use latex2mathml::{latex_to_mathml, DisplayStyle};
 use lol_html::{element, html_content::Element, HtmlRewriter, Settings};
 use nipper::Document; // <-- Add nipper import back
 
+use crate::highlight::Highlighter;
+use crate::image_proxy::proxied_image_src;
+use crate::links::{
+    classify_link, mirror_wiki_url, resolve_attachment_href, resolve_image_src, LinkTarget,
+};
+use crate::toc::content_id;
+use crate::wiki_page::{sort_and_dedup, WikiPageRef};
+
+/// Deployment-dependent choices for how mirrored HTML is rewritten.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderOptions {
+    /// Point `img[src]` at the mirror's `/img` proxy when the host is allowed.
+    pub proxy_images: bool,
+    /// Syntax highlight fenced code blocks with a language tag.
+    pub highlight_code: bool,
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// `$...$`, `$$...$$` and ```` ```math ```` blocks as MathML, or their source if it doesn't parse.
+fn render_math(latex: &str, display: bool) -> String {
+    let style = if display {
+        DisplayStyle::Block
+    } else {
+        DisplayStyle::Inline
+    };
+    latex_to_mathml(latex.trim(), style).unwrap_or_else(|_| {
+        if display {
+            format!(
+                "<pre class=\"math\"><code>{}</code></pre>\n",
+                escape_html(latex)
+            )
+        } else {
+            format!("<code class=\"math\">{}</code>", escape_html(latex))
+        }
+    })
+}
+
+// Mermaid needs a browser to lay out, so show the diagram source with a caption instead.
+fn render_mermaid(source: &str) -> String {
+    format!(
+        "<figure class=\"mermaid\">\n<pre lang=\"mermaid\"><code>{}</code></pre>\n<figcaption>Mermaid diagram source. The diagram is drawn on the original page.</figcaption>\n</figure>\n",
+        escape_html(source)
+    )
+}
+
+/// Render wiki Markdown and rewrite it for the mirror.
+///
+/// Raw HTML in the Markdown is passed through like GitHub does; the result must go
+/// through [`sanitize_html`] before being served.
 pub fn process_markdown(
     original_markdown: &str,
     account: &str,
     repository: &str,
-    homepage_prepend: bool,
+    options: &RenderOptions,
 ) -> String {
-    let mut options = Options::default();
-    options.extension.strikethrough = true;
-    options.extension.tagfilter = true;
-    options.extension.table = true;
-    options.extension.autolink = true;
-    options.extension.tasklist = true;
-    options.extension.header_ids = Some("".to_string());
-    options.render.github_pre_lang = true;
-
-    let original_html = markdown_to_html(original_markdown, &options);
-    process_html(&original_html, account, repository, homepage_prepend)
+    let mut comrak_options = Options::default();
+    comrak_options.extension.strikethrough = true;
+    comrak_options.extension.table = true;
+    comrak_options.extension.autolink = true;
+    comrak_options.extension.tasklist = true;
+    comrak_options.extension.header_ids = Some("".to_string());
+    comrak_options.extension.footnotes = true;
+    comrak_options.extension.alerts = true;
+    comrak_options.extension.math_dollars = true;
+    comrak_options.extension.shortcodes = true;
+    comrak_options.render.github_pre_lang = true;
+    comrak_options.render.r#unsafe = true;
+
+    let highlighter = options.highlight_code.then(Highlighter::new);
+    let arena = Arena::new();
+    let root = parse_document(&arena, original_markdown, &comrak_options);
+    for node in root.descendants() {
+        let mut ast = node.data_mut();
+        let rendered = match &ast.value {
+            NodeValue::Math(math) => render_math(&math.literal, math.display_math),
+            NodeValue::CodeBlock(code_block) => match code_block.info.trim() {
+                "math" => render_math(&code_block.literal, true),
+                "mermaid" => render_mermaid(&code_block.literal),
+                info => {
+                    let language = info.split_whitespace().next().unwrap_or_default();
+                    match highlighter.as_ref().and_then(|highlighter| {
+                        highlighter.highlight(&code_block.literal, language)
+                    }) {
+                        Some(highlighted) => highlighted,
+                        None => continue,
+                    }
+                }
+            },
+            _ => continue,
+        };
+        ast.value = NodeValue::Raw(rendered);
+    }
+
+    let mut original_html = String::new();
+    format_html(root, &comrak_options, &mut original_html)
+        .expect("formatting into a String cannot fail");
+    process_html(&original_html, account, repository, options)
 }
 
 // New lol_html version of process_html
@@ -26,7 +118,7 @@ pub fn process_html(
     original_html: &str,
     account: &str,
     repository: &str,
-    homepage_prepend: bool,
+    options: &RenderOptions,
 ) -> String {
     let mut output = Vec::new();
     let mut rewriter = HtmlRewriter::new(
@@ -34,18 +126,29 @@ pub fn process_html(
             element_content_handlers: vec![
                 element!("a[href]", |el: &mut Element| {
                     if let Some(href) = el.get_attribute("href") {
-                        if !href.starts_with("http://")
-                            && !href.starts_with("https://")
-                            && !href.starts_with("//")
+                        let target = classify_link(&href, account, repository);
+                        if let Some(file_href) = resolve_attachment_href(&href, account, repository)
                         {
-                            if href.starts_with('/') {
-                                let new_href = format!("/m{}", href);
-                                el.set_attribute("href", &new_href).unwrap();
-                            } else if homepage_prepend && !href.starts_with("wiki/") {
-                                let new_href = format!("wiki/{}", href);
-                                el.set_attribute("href", &new_href).unwrap();
-                            }
+                            el.set_attribute("href", &file_href).unwrap();
                         } else {
+                            match &target {
+                                LinkTarget::WikiPage(path) => {
+                                    el.set_attribute("href", &format!("/m{path}")).unwrap();
+                                }
+                                LinkTarget::SameWikiPage(page) => {
+                                    el.set_attribute(
+                                        "href",
+                                        &mirror_wiki_url(account, repository, page),
+                                    )
+                                    .unwrap();
+                                }
+                                LinkTarget::GitHub(url) => {
+                                    el.set_attribute("href", url).unwrap();
+                                }
+                                LinkTarget::Anchor | LinkTarget::External | LinkTarget::Other => {}
+                            }
+                        }
+                        if target == LinkTarget::External {
                             el.set_attribute("rel", "nofollow ugc").unwrap();
                         }
                     }
@@ -53,27 +156,13 @@ pub fn process_html(
                 }),
                 element!("img[src]", |el: &mut Element| {
                     if let Some(src) = el.get_attribute("src") {
-                        if !src.starts_with("http://")
-                            && !src.starts_with("https://")
-                            && !src.starts_with("//")
-                        {
-                            if src.starts_with('/') {
-                                let new_src = format!("https://github.com{}", src);
-                                el.set_attribute("src", &new_src).unwrap();
-                            } else if !src.starts_with("wiki") {
-                                let new_src = format!(
-                                    "https://github.com/{}/{}/wiki/{}",
-                                    account, repository, src
-                                );
-                                el.set_attribute("src", &new_src).unwrap();
-                            } else {
-                                let new_src = format!(
-                                    "https://github.com/{}/{}/{}",
-                                    account, repository, src
-                                );
-                                el.set_attribute("src", &new_src).unwrap();
-                            }
-                        }
+                        let src = resolve_image_src(&src, account, repository).unwrap_or(src);
+                        let src = options
+                            .proxy_images
+                            .then(|| proxied_image_src(&src))
+                            .flatten()
+                            .unwrap_or(src);
+                        el.set_attribute("src", &src).unwrap();
                     }
                     Ok(())
                 }),
@@ -89,20 +178,177 @@ pub fn process_html(
     String::from_utf8(output).unwrap()
 }
 
+// Video players that may be embedded, by `src` prefix.
+const APPROVED_EMBEDS: &[&str] = &[
+    "https://www.youtube.com/embed/",
+    "https://www.youtube-nocookie.com/embed/",
+    "https://player.vimeo.com/video/",
+];
+
+// Presentation MathML as produced for `$...$` math.
+const MATHML_TAGS: [&str; 22] = [
+    "math",
+    "mi",
+    "mn",
+    "mo",
+    "ms",
+    "mtext",
+    "mspace",
+    "mrow",
+    "mfrac",
+    "msqrt",
+    "mroot",
+    "msub",
+    "msup",
+    "msubsup",
+    "munder",
+    "mover",
+    "munderover",
+    "mtable",
+    "mtr",
+    "mtd",
+    "mstyle",
+    "mpadded",
+];
+
+static SANITIZER: LazyLock<Builder<'static>> = LazyLock::new(|| {
+    let mut builder = Builder::default();
+    builder
+        // `process_html` decides on `rel` itself.
+        .link_rel(None)
+        .add_tags(["input", "iframe", "section"])
+        .add_tags(MATHML_TAGS)
+        .add_generic_attributes(["id", "class", "align", "aria-hidden"])
+        .add_tag_attributes("a", ["rel", "name"])
+        .add_tag_attributes("input", ["type", "checked", "disabled"])
+        .add_tag_attributes("math", ["xmlns", "display"])
+        .add_tag_attributes("mo", ["stretchy", "fence", "separator", "lspace", "rspace"])
+        .add_tag_attributes("mi", ["mathvariant"])
+        .add_tag_attributes("mstyle", ["displaystyle", "scriptlevel"])
+        .add_tag_attributes("mfrac", ["linethickness"])
+        .add_tag_attributes("mover", ["accent"])
+        .add_tag_attributes("munder", ["accentunder"])
+        .add_tag_attributes("mtable", ["columnalign"])
+        .add_tag_attributes("mspace", ["width"])
+        .add_tag_attributes(
+            "iframe",
+            ["src", "width", "height", "allowfullscreen", "title"],
+        )
+        .set_tag_attribute_value(
+            "iframe",
+            "sandbox",
+            "allow-scripts allow-same-origin allow-presentation",
+        )
+        .clean_content_tags(HashSet::from([
+            "script", "style", "template", "textarea", "select", "noscript", "object", "embed",
+            "svg",
+        ]))
+        .attribute_filter(|element, attribute, value| match (element, attribute) {
+            // Page ids must not clash with the template's, see `toc::add_heading_anchors`.
+            (_, "id") | ("a", "name") => Some(content_id(value).into()),
+            ("a", "href") if value.len() > 1 && value.starts_with('#') => {
+                Some(format!("#{}", content_id(&value[1..])).into())
+            }
+            // Task list checkboxes are the only inputs GitHub renders.
+            ("input", "type") => (value == "checkbox").then_some(value.into()),
+            ("iframe", "src") => APPROVED_EMBEDS
+                .iter()
+                .any(|embed| value.starts_with(embed))
+                .then_some(value.into()),
+            _ => Some(value.into()),
+        });
+    builder
+});
+
+/// Reduce rendered wiki content to an allowlist of tags, attributes and URL schemes.
+///
+/// Removes scripts, event handlers, `javascript:` URLs, forms and iframes other than
+/// video embeds, and moves ids out of the template's way. Everything shown on the
+/// mirror goes through this.
+pub fn sanitize_html(html: &str) -> String {
+    let cleaned = SANITIZER.clean(html).to_string();
+
+    // Iframes and inputs that lost their `src` or `type` above are empty shells.
+    let mut output = Vec::new();
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![element!(
+                "iframe:not([src]), input:not([type])",
+                |el: &mut Element| {
+                    el.remove();
+                    Ok(())
+                }
+            )],
+            ..Settings::default()
+        },
+        |c: &[u8]| output.extend_from_slice(c),
+    );
+
+    rewriter.write(cleaned.as_bytes()).unwrap();
+    rewriter.end().unwrap();
+
+    String::from_utf8(output).unwrap()
+}
+
 // Original nipper version of process_html_index
-pub fn process_html_index(original_html: &str) -> Vec<(String, String)> {
+pub fn process_html_index(
+    original_html: &str,
+    account: &str,
+    repository: &str,
+) -> Vec<WikiPageRef> {
     let document = Document::from(original_html);
-    document
+    let pages = document
         .select("#wiki-pages-box a, .flex-auto.min-width-0.col-12.col-md-8 a")
         .iter()
         .filter_map(|element| {
-            element
-                .attr("href")
-                .map(|attr_value| (String::from(attr_value), String::from(element.text())))
+            let href = element.attr("href")?;
+            WikiPageRef::from_href(&href, &element.text())
+        })
+        .filter(|page| page.belongs_to(account, repository))
+        .collect();
+    sort_and_dedup(pages)
+}
+
+// Every distinct link target in rendered content, in document order.
+pub fn extract_links(rendered_html: &str) -> Vec<String> {
+    let document = Document::from(rendered_html);
+    let mut links: Vec<String> = Vec::new();
+    for href in document
+        .select("a[href]")
+        .iter()
+        .filter_map(|element| element.attr("href"))
+    {
+        if !href.is_empty() && !links.iter().any(|link| **link == *href) {
+            links.push(String::from(href));
+        }
+    }
+    links
+}
+
+// Page listings (`/wiki/_pages`) show a "Last updated" time on each row.
+pub fn process_html_index_last_updated(original_html: &str) -> Vec<(WikiPageRef, String)> {
+    let document = Document::from(original_html);
+    document
+        .select("li.Box-row")
+        .iter()
+        .filter_map(|row| {
+            let link = row.select("a[href]");
+            let page = WikiPageRef::from_href(&link.attr("href")?, &link.text())?;
+            let datetime = row.select("relative-time[datetime]").attr("datetime")?;
+            Some((page, String::from(datetime)))
         })
         .collect()
 }
 
+// Large page listings are paginated.
+pub fn process_html_index_next_page(original_html: &str) -> Option<String> {
+    let document = Document::from(original_html);
+    document
+        .select(".pagination a.next_page")
+        .attr("href")
+        .map(String::from)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -112,7 +358,7 @@ mod tests {
         let html = "<html><head></head><body><a href=\"https://example.com\"></a></body></html>";
 
         assert_eq!(
-            process_html(html, "some_account", "some_repo", false),
+            process_html(html, "some_account", "some_repo", &RenderOptions::default()),
             "<html><head></head><body><a href=\"https://example.com\" rel=\"nofollow ugc\"></a></body></html>"
         );
     }
@@ -122,7 +368,7 @@ mod tests {
         let html = "<html><head></head><body><a href=\"//example.com\"></a></body></html>";
 
         assert_eq!(
-            process_html(html, "some_account", "some_repo", false),
+            process_html(html, "some_account", "some_repo", &RenderOptions::default()),
             "<html><head></head><body><a href=\"//example.com\" rel=\"nofollow ugc\"></a></body></html>"
         );
     }
@@ -132,18 +378,38 @@ mod tests {
         let html = "<html><head></head><body><a href=\"http://example.com\"></a></body></html>";
 
         assert_eq!(
-            process_html(html, "some_account", "some_repo", false),
+            process_html(html, "some_account", "some_repo", &RenderOptions::default()),
             "<html><head></head><body><a href=\"http://example.com\" rel=\"nofollow ugc\"></a></body></html>"
         );
     }
 
+    #[test]
+    fn transform_relative_wiki_links_to_absolute() {
+        let html = "<a href=\"Setup#linux\">Setup</a><a href=\"../wiki/FAQ\">FAQ</a><a href=\"wiki/Home\">Home</a>";
+
+        assert_eq!(
+            process_html(html, "some_account", "some_repo", &RenderOptions::default()),
+            "<a href=\"/m/some_account/some_repo/wiki/Setup#linux\">Setup</a><a href=\"/m/some_account/some_repo/wiki/FAQ\">FAQ</a><a href=\"/m/some_account/some_repo/wiki\">Home</a>"
+        );
+    }
+
+    #[test]
+    fn transform_github_links() {
+        let html = "<a href=\"https://github.com/other/repo/wiki/Page#setup\">Other wiki</a><a href=\"/other/repo/wiki\">Other home</a><a href=\"./Page\">Same wiki</a><a href=\"#top\">Top</a><a href=\"https://github.com/other/repo/blob/main/src/lib.rs\">Code</a><a href=\"/other/repo/issues\">Issues</a><a href=\"mailto:a@example.com\">Mail</a>";
+
+        assert_eq!(
+            process_html(html, "some_account", "some_repo", &RenderOptions::default()),
+            "<a href=\"/m/other/repo/wiki/Page#setup\">Other wiki</a><a href=\"/m/other/repo/wiki\">Other home</a><a href=\"/m/some_account/some_repo/wiki/Page\">Same wiki</a><a href=\"#top\">Top</a><a href=\"https://github.com/other/repo/blob/main/src/lib.rs\">Code</a><a href=\"https://github.com/other/repo/issues\">Issues</a><a href=\"mailto:a@example.com\">Mail</a>"
+        );
+    }
+
     #[test]
     fn transform_img_src_to_github_root() {
         let html = "<html><head></head><body><img src=\"/Erithano/Timon-Your-FAQ-bot-for-Microsoft-Teams/wiki/images/Guide1.1.jpg\"></body></html>";
 
         assert_eq!(
-            process_html(html, "some_account", "some_repo", false),
-            "<html><head></head><body><img src=\"https://github.com/Erithano/Timon-Your-FAQ-bot-for-Microsoft-Teams/wiki/images/Guide1.1.jpg\"></body></html>"
+            process_html(html, "some_account", "some_repo", &RenderOptions::default()),
+            "<html><head></head><body><img src=\"https://raw.githubusercontent.com/wiki/Erithano/Timon-Your-FAQ-bot-for-Microsoft-Teams/images/Guide1.1.jpg\"></body></html>"
         );
     }
 
@@ -154,8 +420,8 @@ mod tests {
             "<html><head></head><body><img src=\"wiki/images/false-icon.png\"></body></html>";
 
         assert_eq!(
-            process_html(html, "some_account", "some_repo", false),
-            "<html><head></head><body><img src=\"https://github.com/some_account/some_repo/wiki/images/false-icon.png\"></body></html>"
+            process_html(html, "some_account", "some_repo", &RenderOptions::default()),
+            "<html><head></head><body><img src=\"https://raw.githubusercontent.com/wiki/some_account/some_repo/images/false-icon.png\"></body></html>"
         );
     }
 
@@ -164,7 +430,7 @@ mod tests {
         let html = "<html><head></head><body><img src=\"https://camo.githubusercontent.com/\"></body></html>";
 
         assert_eq!(
-            process_html(html, "some_account", "some_repo", false),
+            process_html(html, "some_account", "some_repo", &RenderOptions::default()),
             "<html><head></head><body><img src=\"https://camo.githubusercontent.com/\"></body></html>"
         );
     }
@@ -175,8 +441,233 @@ mod tests {
         let html = "<html><head></head><body><img src=\"images/something.png\"></body></html>";
 
         assert_eq!(
-            process_html(html, "some_account", "some_repo", false),
-            "<html><head></head><body><img src=\"https://github.com/some_account/some_repo/wiki/images/something.png\"></body></html>"
+            process_html(html, "some_account", "some_repo", &RenderOptions::default()),
+            "<html><head></head><body><img src=\"https://raw.githubusercontent.com/wiki/some_account/some_repo/images/something.png\"></body></html>"
+        );
+    }
+
+    #[test]
+    fn transform_img_src_proxied() {
+        let html = "<img src=\"images/a.png\"><img src=\"https://example.com/b.png\">";
+        let options = RenderOptions {
+            proxy_images: true,
+            ..RenderOptions::default()
+        };
+
+        assert_eq!(
+            process_html(html, "some_account", "some_repo", &options),
+            "<img src=\"/img?url=https%3A%2F%2Fraw%2Egithubusercontent%2Ecom%2Fwiki%2Fsome%5Faccount%2Fsome%5Frepo%2Fimages%2Fa%2Epng\"><img src=\"https://example.com/b.png\">"
+        );
+    }
+
+    #[test]
+    fn transform_img_src_user_attachments_untouched() {
+        let html = "<img src=\"https://github.com/user-attachments/assets/5e3b2d4c-1f\">";
+
+        assert_eq!(
+            process_html(html, "some_account", "some_repo", &RenderOptions::default()),
+            html
+        );
+    }
+
+    #[test]
+    fn transform_img_src_blob_to_raw() {
+        let html = "<img src=\"https://github.com/Navid200/xDrip/blob/master/Documentation/images/Releases.png\">";
+
+        assert_eq!(
+            process_html(html, "some_account", "some_repo", &RenderOptions::default()),
+            "<img src=\"https://github.com/Navid200/xDrip/raw/master/Documentation/images/Releases.png\">"
+        );
+    }
+
+    #[test]
+    fn transform_attachment_links_to_raw() {
+        let html = "<a href=\"files/manual.pdf\">Manual</a><a href=\"https://github.com/some_account/some_repo/wiki/release.zip\">Zip</a><a href=\"Some-Page\">Page</a>";
+
+        assert_eq!(
+            process_html(html, "some_account", "some_repo", &RenderOptions::default()),
+            "<a href=\"https://raw.githubusercontent.com/wiki/some_account/some_repo/files/manual.pdf\">Manual</a><a href=\"https://raw.githubusercontent.com/wiki/some_account/some_repo/release.zip\">Zip</a><a href=\"/m/some_account/some_repo/wiki/Some-Page\">Page</a>"
+        );
+    }
+
+    #[test]
+    fn sanitize_strips_scripts_and_handlers() {
+        let cases = [
+            ("<p>hi<script>alert(1)</script></p>", "<p>hi</p>"),
+            (
+                "<img src=\"a.png\" onerror=\"alert(1)\">",
+                "<img src=\"a.png\">",
+            ),
+            ("<a href=\"javascript:alert(1)\">x</a>", "<a>x</a>"),
+            ("<a href=\"java&#x09;script:alert(1)\">x</a>", "<a>x</a>"),
+            ("<a href=\" JAVASCRIPT:alert(1)\">x</a>", "<a>x</a>"),
+            ("<img src=\"data:text/html;base64,PHNjcmlwdD4=\">", "<img>"),
+            (
+                "<svg onload=\"alert(1)\"><circle></circle></svg>after",
+                "after",
+            ),
+            (
+                "<div style=\"background:url(javascript:alert(1))\">x</div>",
+                "<div>x</div>",
+            ),
+            ("<style>body{display:none}</style><p>x</p>", "<p>x</p>"),
+            (
+                "<math><mi xlink:href=\"javascript:alert(1)\">x</mi></math>",
+                "<math><mi>x</mi></math>",
+            ),
+            ("<p>a</p><!-- <script>alert(1)</script> -->", "<p>a</p>"),
+        ];
+        for (html, expected) in cases {
+            assert_eq!(sanitize_html(html), expected, "{html}");
+        }
+    }
+
+    #[test]
+    fn sanitize_strips_forms_and_iframes() {
+        assert_eq!(
+            sanitize_html(
+                "<form action=\"https://example.com\"><input type=\"password\" name=\"p\"><button>Go</button></form>"
+            ),
+            "Go"
+        );
+        assert_eq!(
+            sanitize_html("<p><iframe src=\"https://example.com/phish\">fallback</iframe><input value=\"x\"></p>"),
+            "<p></p>"
+        );
+        assert_eq!(
+            sanitize_html(
+                "<iframe src=\"https://www.youtube.com/embed/dQw4w9WgXcQ\" onload=\"alert(1)\" allowfullscreen></iframe>"
+            ),
+            "<iframe src=\"https://www.youtube.com/embed/dQw4w9WgXcQ\" allowfullscreen=\"\" sandbox=\"allow-scripts allow-same-origin allow-presentation\"></iframe>"
+        );
+    }
+
+    #[test]
+    fn sanitize_moves_ids_out_of_the_templates_way() {
+        assert_eq!(
+            sanitize_html("<div id=\"content\" class=\"x\"><a name=\"index\" href=\"#content\">a</a><a href=\"#\">top</a></div>"),
+            "<div id=\"user-content-content\" class=\"x\"><a name=\"user-content-index\" href=\"#user-content-content\">a</a><a href=\"#\">top</a></div>"
+        );
+    }
+
+    #[test]
+    fn sanitize_keeps_rendered_markdown() {
+        let html = process_markdown(
+            "# Title\n\n- [x] done\n\n| a |\n|:-|\n| b |\n\n```rust\nfn main() {}\n```\n\n[ext](https://example.com)",
+            "some_account",
+            "some_repo",
+            &RenderOptions::default(),
+        );
+
+        let sanitized = sanitize_html(&html);
+        for kept in [
+            "<h1><a href=\"#user-content-title\" aria-hidden=\"true\" class=\"anchor\" id=\"user-content-title\"></a>Title</h1>",
+            "<input type=\"checkbox\" checked=\"\" disabled=\"\">",
+            "<th align=\"left\">a</th>",
+            "<pre lang=\"rust\"><code>fn main() {}\n</code></pre>",
+            "<a href=\"https://example.com\" rel=\"nofollow ugc\">ext</a>",
+        ] {
+            assert!(sanitized.contains(kept), "{kept} in {sanitized}");
+        }
+    }
+
+    #[test]
+    fn markdown_raw_html_is_rendered_then_sanitized() {
+        let html = process_markdown(
+            "<details><summary>More</summary>\n\n*hidden* <img src=\"a.png\" onerror=\"alert(1)\">\n\n</details>\n\n<script>alert(1)</script>\n\nSee <https://example.com/x>.",
+            "some_account",
+            "some_repo",
+            &RenderOptions::default(),
+        );
+
+        assert_eq!(
+            sanitize_html(&html),
+            "<details><summary>More</summary>\n<p><em>hidden</em> <img src=\"https://raw.githubusercontent.com/wiki/some_account/some_repo/a.png\"></p>\n</details>\n\n<p>See <a href=\"https://example.com/x\" rel=\"nofollow ugc\">https://example.com/x</a>.</p>\n"
+        );
+    }
+
+    fn render_gfm_fixture(markdown: &str) -> String {
+        sanitize_html(&process_markdown(
+            markdown,
+            "some_account",
+            "some_repo",
+            &RenderOptions::default(),
+        ))
+    }
+
+    #[test]
+    fn highlight_code_blocks() {
+        let options = RenderOptions {
+            highlight_code: true,
+            ..RenderOptions::default()
+        };
+        let html = sanitize_html(&process_markdown(
+            "```python\nprint(\"<hi>\")\n```\n\n```unknown-language\nx\n```",
+            "some_account",
+            "some_repo",
+            &options,
+        ));
+
+        assert!(html.starts_with("<pre lang=\"python\"><code><span class=\"hl-source hl-python\">"));
+        assert!(html.contains("&lt;hi&gt;"));
+        assert!(html.ends_with("<pre lang=\"unknown-language\"><code>x\n</code></pre>\n"));
+    }
+
+    #[test]
+    fn gfm_alerts() {
+        assert_eq!(
+            render_gfm_fixture(include_str!("../test-data/gfm/alerts.md")),
+            include_str!("../test-data/gfm/alerts.html")
+        );
+    }
+
+    #[test]
+    fn gfm_footnotes() {
+        assert_eq!(
+            render_gfm_fixture(include_str!("../test-data/gfm/footnotes.md")),
+            include_str!("../test-data/gfm/footnotes.html")
+        );
+    }
+
+    #[test]
+    fn gfm_math() {
+        assert_eq!(
+            render_gfm_fixture(include_str!("../test-data/gfm/math.md")),
+            include_str!("../test-data/gfm/math.html")
+        );
+        assert_eq!(
+            render_gfm_fixture("$\\frac{1$"),
+            "<p><code class=\"math\">\\frac{1</code></p>\n"
+        );
+    }
+
+    #[test]
+    fn gfm_shortcodes() {
+        assert_eq!(
+            render_gfm_fixture(include_str!("../test-data/gfm/shortcodes.md")),
+            include_str!("../test-data/gfm/shortcodes.html")
+        );
+    }
+
+    #[test]
+    fn gfm_mermaid() {
+        assert_eq!(
+            render_gfm_fixture(include_str!("../test-data/gfm/mermaid.md")),
+            include_str!("../test-data/gfm/mermaid.html")
+        );
+    }
+
+    #[test]
+    fn markdown_wiki_image_with_attributes() {
+        let md = crate::gh_extensions::github_wiki_markdown_to_pure_markdown(
+            "[[images/logo.png|alt=Logo|width=100|align=center]]",
+            "a",
+            "r",
+        );
+
+        assert_eq!(
+            process_markdown(&md, "a", "r", &RenderOptions::default()),
+            "<p><span><img src=\"https://raw.githubusercontent.com/wiki/a/r/images/logo.png\" alt=\"Logo\" width=\"100\" align=\"center\"></span></p>\n"
         );
     }
 
@@ -184,26 +675,70 @@ mod tests {
     fn get_page_list() {
         let html = include_str!("../test-data/wiki-index.html");
 
-        let pages = process_html_index(html);
+        let pages = process_html_index(html, "nelsonjchen", "github-wiki-test");
         assert!(pages.len() > 3);
         let page_1 = pages.first().unwrap();
-        assert!(page_1.0.contains("nelsonjchen"));
-        assert!(page_1.0.contains("wiki"));
-        assert_eq!(page_1.1.trim(), "Home");
+        assert_eq!(page_1.account, "nelsonjchen");
+        assert_eq!(page_1.github_path(), "/nelsonjchen/github-wiki-test/wiki");
+        assert_eq!(page_1.title, "Home");
+        assert!(pages
+            .iter()
+            .any(|page| page.slug == "What's-up" && page.title == "What's up"));
+        assert!(process_html_index(html, "nelsonjchen", "other-wiki").is_empty());
     }
 
     #[test]
     fn get_page_list_homeless() {
         let html = include_str!("../test-data/wiki-homeless-index.html");
 
-        let pages = process_html_index(html);
+        let pages = process_html_index(html, "nelsonjchen", "github-wiki-test-homeless");
         use more_asserts::assert_ge;
         assert_ge!(pages.len(), 3);
-        assert!(pages.first().unwrap().0.contains("Homeless"));
-        assert_eq!(pages.first().unwrap().1.trim(), "Homeless");
-        assert!(pages.get(1).unwrap().0.contains("Ooze"));
-        assert_eq!(pages.get(1).unwrap().1.trim(), "Ooze");
-        assert!(pages.get(2).unwrap().0.contains("Porkchops"));
-        assert_eq!(pages.get(2).unwrap().1.trim(), "Porkchops");
+        assert_eq!(pages.first().unwrap().slug, "Homeless");
+        assert_eq!(pages.first().unwrap().title, "Homeless");
+        assert_eq!(pages.get(1).unwrap().slug, "Ooze");
+        assert_eq!(pages.get(1).unwrap().title, "Ooze");
+        assert_eq!(pages.get(2).unwrap().slug, "Porkchops");
+        assert_eq!(pages.get(2).unwrap().title, "Porkchops");
+    }
+
+    #[test]
+    fn get_page_list_last_updated() {
+        let html = include_str!("../test-data/wiki-homeless-index.html");
+
+        let last_updated = process_html_index_last_updated(html);
+        let (page, datetime) = last_updated.first().unwrap();
+        assert_eq!(page.slug, "Homeless");
+        assert_eq!(datetime, "2021-10-25T02:27:52Z");
+        assert_eq!(last_updated.len(), 3);
+    }
+
+    #[test]
+    fn get_page_list_large() {
+        let html = include_str!("../test-data/wiki-pages-large.html");
+
+        let pages = process_html_index(html, "nelsonjchen", "github-wiki-test-large");
+        assert_eq!(pages.len(), 300);
+        assert_eq!(
+            process_html_index_next_page(html).as_deref(),
+            Some("/nelsonjchen/github-wiki-test-large/wiki/_pages?page=2")
+        );
+
+        let last_html = include_str!("../test-data/wiki-pages-large-2.html");
+        assert_eq!(
+            process_html_index(last_html, "nelsonjchen", "github-wiki-test-large").len(),
+            150
+        );
+        assert_eq!(process_html_index_next_page(last_html), None);
+    }
+
+    #[test]
+    fn extract_distinct_links() {
+        let html = "<p><a href=\"/m/a/r/wiki/One\">1</a> <a href=\"https://example.com\">2</a> <a href=\"/m/a/r/wiki/One\">1 again</a> <a name=\"anchor\">no href</a></p>";
+
+        assert_eq!(
+            extract_links(html),
+            ["/m/a/r/wiki/One", "https://example.com"]
+        );
     }
 }
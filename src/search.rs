@@ -0,0 +1,315 @@
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use percent_encoding::utf8_percent_encode;
+
+use scraper::Html;
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{BooleanQuery, Occur, Query, QueryParser, TermQuery};
+use tantivy::schema::{
+    Field, IndexRecordOption, Schema, TantivyDocument, Value, STORED, STRING, TEXT,
+};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, Term};
+
+use crate::cache::TtlCache;
+use crate::NON_ALPHANUMERIC_GH;
+
+const WRITER_MEMORY_BYTES: usize = 50_000_000;
+/// Pages waiting for the next commit; more are dropped until then.
+const MAX_PENDING_PAGES: usize = 512;
+const MAX_RESULTS: usize = 20;
+const SNIPPET_CHARS: usize = 200;
+/// How often newly indexed pages become searchable.
+pub const COMMIT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a queued page isn't queued again; popular pages are viewed far more often than
+/// they change.
+const REINDEX_AFTER: Duration = Duration::from_secs(10 * 60);
+
+struct Fields {
+    /// `account/repository/page`, unique per document.
+    key: Field,
+    /// `account/repository` in lowercase, for searching one wiki.
+    wiki: Field,
+    account: Field,
+    repository: Field,
+    page: Field,
+    title: Field,
+    body: Field,
+}
+
+fn schema() -> (Schema, Fields) {
+    let mut builder = Schema::builder();
+    let fields = Fields {
+        key: builder.add_text_field("key", STRING),
+        wiki: builder.add_text_field("wiki", STRING),
+        account: builder.add_text_field("account", STORED),
+        repository: builder.add_text_field("repository", STORED),
+        page: builder.add_text_field("page", STORED),
+        title: builder.add_text_field("title", TEXT | STORED),
+        body: builder.add_text_field("body", TEXT | STORED),
+    };
+    (builder.build(), fields)
+}
+
+/// A page matching a search.
+pub struct SearchResult {
+    pub account: String,
+    pub repository: String,
+    pub page: String,
+    pub title: String,
+    /// Escaped excerpt with matching words in `<b>`.
+    pub snippet: String,
+}
+
+impl SearchResult {
+    pub fn mirror_path(&self) -> String {
+        let page = utf8_percent_encode(&self.page, NON_ALPHANUMERIC_GH).to_string();
+        crate::links::mirror_wiki_url(&self.account, &self.repository, &page)
+    }
+}
+
+struct PendingPage {
+    key: String,
+    account: String,
+    repository: String,
+    page: String,
+    title: String,
+    html: String,
+}
+
+/// Full-text index of the pages this mirror has rendered.
+///
+/// Pages are only queued when viewed; the writer is used by [`SearchIndex::commit`] alone,
+/// which should run off the async workers.
+pub struct SearchIndex {
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    fields: Fields,
+    pending: SyncSender<PendingPage>,
+    queued: Mutex<Receiver<PendingPage>>,
+    /// Keys of pages queued lately.
+    recently_indexed: TtlCache<String, ()>,
+}
+
+// GitHub treats account and repository names case-insensitively.
+fn wiki_key(account: &str, repository: &str) -> String {
+    format!("{}/{}", account.to_lowercase(), repository.to_lowercase())
+}
+
+fn page_key(account: &str, repository: &str, page: &str) -> String {
+    format!("{}/{page}", wiki_key(account, repository))
+}
+
+/// Visible text of rendered page HTML.
+fn html_text(html: &str) -> String {
+    Html::parse_fragment(html)
+        .root_element()
+        .text()
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl SearchIndex {
+    /// Index stored in `path`, or only in memory without one.
+    pub fn open(path: Option<&Path>) -> tantivy::Result<Self> {
+        let (schema, fields) = schema();
+        let index = match path {
+            Some(path) => {
+                std::fs::create_dir_all(path)?;
+                Index::open_or_create(MmapDirectory::open(path)?, schema)?
+            }
+            None => Index::create_in_ram(schema),
+        };
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let writer = index.writer_with_num_threads(1, WRITER_MEMORY_BYTES)?;
+        let (pending, queued) = sync_channel(MAX_PENDING_PAGES);
+
+        Ok(Self {
+            index,
+            reader,
+            writer: Mutex::new(writer),
+            fields,
+            pending,
+            queued: Mutex::new(queued),
+            recently_indexed: TtlCache::new(REINDEX_AFTER, 4096),
+        })
+    }
+
+    /// Queue a page to be added or replaced. It becomes searchable after the next
+    /// [`commit`](Self::commit), unless too many pages are already waiting.
+    pub fn index_page(&self, account: &str, repository: &str, page: &str, title: &str, html: &str) {
+        let key = page_key(account, repository, page);
+        if self.recently_indexed.get(&key).is_some() {
+            return;
+        }
+
+        let queued = self.pending.try_send(PendingPage {
+            key: key.clone(),
+            account: account.to_string(),
+            repository: repository.to_string(),
+            page: page.to_string(),
+            title: title.to_string(),
+            html: html.to_string(),
+        });
+        // A dropped page is queued again when it is next viewed.
+        if queued.is_ok() {
+            self.recently_indexed.insert(key, ());
+        }
+    }
+
+    /// Write queued pages to the index and make them searchable. Blocks on disk writes.
+    pub fn commit(&self) -> tantivy::Result<()> {
+        let queued = self.queued.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let fields = &self.fields;
+        let mut added = false;
+        for page in queued.try_iter() {
+            writer.delete_term(Term::from_field_text(fields.key, &page.key));
+            writer.add_document(doc!(
+                fields.key => page.key,
+                fields.wiki => wiki_key(&page.account, &page.repository),
+                fields.account => page.account,
+                fields.repository => page.repository,
+                fields.page => page.page,
+                fields.title => page.title,
+                fields.body => html_text(&page.html),
+            ))?;
+            added = true;
+        }
+        if !added {
+            return Ok(());
+        }
+        writer.commit()?;
+        self.reader.reload()
+    }
+
+    /// Best matches for `query`, in every wiki or only `account/repository`.
+    pub fn search(
+        &self,
+        query: &str,
+        wiki: Option<(&str, &str)>,
+    ) -> tantivy::Result<Vec<SearchResult>> {
+        let fields = &self.fields;
+        let mut parser = QueryParser::for_index(&self.index, vec![fields.title, fields.body]);
+        parser.set_field_boost(fields.title, 2.0);
+        // Visitors type all sorts of things; search for what could be understood.
+        let (text_query, _errors) = parser.parse_query_lenient(query);
+
+        let query: Box<dyn Query> = match wiki {
+            Some((account, repository)) => Box::new(BooleanQuery::new(vec![
+                (Occur::Must, text_query),
+                (
+                    Occur::Must,
+                    Box::new(TermQuery::new(
+                        Term::from_field_text(fields.wiki, &wiki_key(account, repository)),
+                        IndexRecordOption::Basic,
+                    )),
+                ),
+            ])),
+            None => text_query,
+        };
+
+        let searcher = self.reader.searcher();
+        let top_docs =
+            searcher.search(&query, &TopDocs::with_limit(MAX_RESULTS).order_by_score())?;
+        let mut snippets = SnippetGenerator::create(&searcher, &query, fields.body)?;
+        snippets.set_max_num_chars(SNIPPET_CHARS);
+
+        top_docs
+            .into_iter()
+            .map(|(_score, address)| {
+                let doc: TantivyDocument = searcher.doc(address)?;
+                let text = |field| {
+                    doc.get_first(field)
+                        .and_then(|value| value.as_str())
+                        .unwrap_or_default()
+                        .to_string()
+                };
+                Ok(SearchResult {
+                    account: text(fields.account),
+                    repository: text(fields.repository),
+                    page: text(fields.page),
+                    title: text(fields.title),
+                    snippet: snippets.snippet_from_doc(&doc).to_html(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_pages() {
+        let index = SearchIndex::open(None).unwrap();
+        index.index_page(
+            "a",
+            "r",
+            "Install",
+            "Installing",
+            "<h1>Install</h1><p>Run <code>cargo install</code> to get <b>started</b>.</p>",
+        );
+        index.index_page(
+            "b",
+            "r",
+            "Home",
+            "Home",
+            "<p>See the install guide & FAQ.</p>",
+        );
+        index.index_page("b", "r", "FAQ", "FAQ", "<p>Nothing here.</p>");
+        index.index_page("b", "r", "C#", "C#", "<p>Sharp install notes.</p>");
+
+        assert!(index.search("install", None).unwrap().is_empty());
+        index.commit().unwrap();
+
+        let results = index.search("install", None).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].mirror_path(), "/m/a/r/wiki/Install");
+        assert_eq!(
+            results[0].snippet,
+            "<b>Install</b> Run cargo <b>install</b> to get started"
+        );
+        assert!(results
+            .iter()
+            .any(|result| result.mirror_path() == "/m/b/r/wiki"
+                && result.snippet == "See the <b>install</b> guide &amp; FAQ"));
+        assert!(results
+            .iter()
+            .any(|result| result.mirror_path() == "/m/b/r/wiki/C%23"));
+
+        let results = index.search("guide", Some(("B", "R"))).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].page, "Home");
+
+        // Unbalanced syntax is searched for as well as possible.
+        assert_eq!(index.search("faq\"", Some(("b", "r"))).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn indexes_are_independent() {
+        let first = SearchIndex::open(None).unwrap();
+        first.index_page("a", "r", "Home", "Home", "<p>Shared words.</p>");
+        first.commit().unwrap();
+
+        let second = SearchIndex::open(None).unwrap();
+        second.index_page("a", "r", "Home", "Home", "<p>Shared words.</p>");
+        // The same page under another spelling of the wiki is the same document.
+        second.index_page("A", "R", "Home", "Home", "<p>Shared words.</p>");
+        second.commit().unwrap();
+
+        assert_eq!(second.search("shared", None).unwrap().len(), 1);
+        assert_eq!(second.search("shared", Some(("A", "r"))).unwrap().len(), 1);
+    }
+}
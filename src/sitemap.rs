@@ -0,0 +1,115 @@
+use std::io::Cursor;
+
+use quick_xml::events::BytesText;
+use quick_xml::Writer;
+
+use crate::retrieval::ContentError;
+
+const SITEMAP_XMLNS: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+
+pub struct SitemapUrl {
+    /// Absolute URL of the page.
+    pub loc: String,
+    /// W3C datetime of the last edit, if GitHub told us.
+    pub lastmod: Option<String>,
+}
+
+fn xml_error(error: impl ToString) -> ContentError {
+    ContentError::OtherError(error.to_string())
+}
+
+fn into_string(writer: Writer<Cursor<Vec<u8>>>) -> Result<String, ContentError> {
+    String::from_utf8(writer.into_inner().into_inner()).map_err(xml_error)
+}
+
+pub fn write_urlset(urls: &[SitemapUrl]) -> Result<String, ContentError> {
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+
+    writer
+        .create_element("urlset")
+        .with_attribute(("xmlns", SITEMAP_XMLNS))
+        .with_attribute(("xmlns:xsi", "http://www.w3.org/2001/XMLSchema-instance"))
+        .with_attribute(("xsi:schemaLocation", "http://www.sitemaps.org/schemas/sitemap/0.9 http://www.sitemaps.org/schemas/sitemap/0.9/sitemap.xsd"))
+        .write_inner_content(|writer| {
+            for url in urls {
+                writer.create_element("url").write_inner_content(|writer| {
+                    writer
+                        .create_element("loc")
+                        .write_text_content(BytesText::new(&url.loc))?;
+                    if let Some(lastmod) = &url.lastmod {
+                        writer
+                            .create_element("lastmod")
+                            .write_text_content(BytesText::new(lastmod))?;
+                    }
+                    Ok(())
+                })?;
+            }
+            Ok(())
+        })
+        .map_err(xml_error)?;
+
+    into_string(writer)
+}
+
+pub fn write_sitemap_index(sitemap_urls: &[String]) -> Result<String, ContentError> {
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+
+    writer
+        .create_element("sitemapindex")
+        .with_attribute(("xmlns", SITEMAP_XMLNS))
+        .write_inner_content(|writer| {
+            for sitemap_url in sitemap_urls {
+                writer
+                    .create_element("sitemap")
+                    .write_inner_content(|writer| {
+                        writer
+                            .create_element("loc")
+                            .write_text_content(BytesText::new(sitemap_url))?;
+                        Ok(())
+                    })?;
+            }
+            Ok(())
+        })
+        .map_err(xml_error)?;
+
+    into_string(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urlset_with_lastmod() {
+        let xml = write_urlset(&[
+            SitemapUrl {
+                loc: "https://example.com/m/a/r/wiki".to_string(),
+                lastmod: Some("2021-12-07T05:17:05Z".to_string()),
+            },
+            SitemapUrl {
+                loc: "https://example.com/m/a/r/wiki/What's-up&more".to_string(),
+                lastmod: None,
+            },
+        ])
+        .unwrap();
+
+        assert!(xml.starts_with("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\""));
+        assert!(xml.contains(
+            "<url><loc>https://example.com/m/a/r/wiki</loc><lastmod>2021-12-07T05:17:05Z</lastmod></url>"
+        ));
+        assert!(xml.contains(
+            "<url><loc>https://example.com/m/a/r/wiki/What&apos;s-up&amp;more</loc></url>"
+        ));
+    }
+
+    #[test]
+    fn sitemap_index() {
+        let xml =
+            write_sitemap_index(&["https://example.com/m/a/r/sitemap.xml".to_string()]).unwrap();
+
+        assert_eq!(
+            xml,
+            "<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\"><sitemap><loc>https://example.com/m/a/r/sitemap.xml</loc></sitemap></sitemapindex>"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use std::sync::LazyLock;
+
+use comrak::Anchorizer;
+use lol_html::{element, html_content::Element, HtmlRewriter, Settings};
+use scraper::{Html, Selector};
+
+use crate::scraper::escape_html;
+
+static HEADING_SELECTOR: LazyLock<Selector> = LazyLock::new(|| {
+    Selector::parse("h1, h2, h3, h4, h5, h6").expect("heading selector should compile")
+});
+
+// GitHub prefixes ids in rendered HTML and resolves `#anchor` to them with JavaScript.
+// The prefix also keeps page ids apart from the template's, like `content` or `index`.
+const GITHUB_ID_PREFIX: &str = "user-content-";
+
+/// `id` moved out of the way of the mirror's own ids, as GitHub does.
+pub fn content_id(id: &str) -> String {
+    if id.starts_with(GITHUB_ID_PREFIX) {
+        id.to_string()
+    } else {
+        format!("{GITHUB_ID_PREFIX}{id}")
+    }
+}
+
+// `#anchor`, or a mirror page link with one, pointing at the prefixed id instead.
+fn content_href(href: &str) -> Option<String> {
+    let (page, anchor) = href.split_once('#')?;
+    if anchor.is_empty() || !(page.is_empty() || page.starts_with("/m/")) {
+        return None;
+    }
+    Some(format!("{page}#{}", content_id(anchor)))
+}
+
+/// A heading of a mirrored page.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TocEntry {
+    pub level: usize,
+    pub id: String,
+    pub title: String,
+}
+
+fn heading_level(tag_name: &str) -> usize {
+    tag_name[1..].parse().unwrap_or(1)
+}
+
+fn collect_headings(html: &str) -> Vec<TocEntry> {
+    let fragment = Html::parse_fragment(html);
+    let mut anchorizer = Anchorizer::new();
+    fragment
+        .select(&HEADING_SELECTOR)
+        .map(|heading| {
+            let title = heading.text().collect::<String>().trim().to_string();
+            // Same slugs and `-1`, `-2` suffixes as GitHub, in document order.
+            let slug = anchorizer.anchorize(&title);
+            let id = content_id(heading.value().attr("id").unwrap_or(&slug));
+            TocEntry {
+                level: heading_level(heading.value().name()),
+                id,
+                title,
+            }
+        })
+        .collect()
+}
+
+/// Put an id on every heading so the original wiki's `#anchor` links work, and
+/// list the headings for a table of contents.
+///
+/// The ids comrak and GitHub put on the hidden `.anchor` links move to the headings.
+/// Like on GitHub, every id starts with `user-content-`, and links to anchors are
+/// pointed at them.
+pub fn add_heading_anchors(html: &str) -> (String, Vec<TocEntry>) {
+    let entries = collect_headings(html);
+    let mut ids = entries.iter().map(|entry| entry.id.as_str());
+
+    let mut output = Vec::new();
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![
+                element!("h1, h2, h3, h4, h5, h6", |el: &mut Element| {
+                    if let Some(id) = ids.next() {
+                        el.set_attribute("id", id).unwrap();
+                    }
+                    Ok(())
+                }),
+                element!("a.anchor[id]", |el: &mut Element| {
+                    el.remove_attribute("id");
+                    Ok(())
+                }),
+                element!("[id], a[name]", |el: &mut Element| {
+                    for attribute in ["id", "name"] {
+                        if let Some(value) = el.get_attribute(attribute) {
+                            el.set_attribute(attribute, &content_id(&value)).unwrap();
+                        }
+                    }
+                    Ok(())
+                }),
+                element!("a[href*='#']", |el: &mut Element| {
+                    if let Some(href) = el
+                        .get_attribute("href")
+                        .and_then(|href| content_href(&href))
+                    {
+                        el.set_attribute("href", &href).unwrap();
+                    }
+                    Ok(())
+                }),
+            ],
+            ..Settings::default()
+        },
+        |c: &[u8]| output.extend_from_slice(c),
+    );
+
+    rewriter.write(html.as_bytes()).unwrap();
+    rewriter.end().unwrap();
+
+    (String::from_utf8(output).unwrap(), entries)
+}
+
+/// Collapsible, nested list of links to the headings, if there are enough to need one.
+pub fn render_toc(entries: &[TocEntry]) -> Option<String> {
+    if entries.len() < 2 {
+        return None;
+    }
+
+    let mut html = String::from("<details id=\"toc\"><summary>Contents</summary>\n");
+    let mut levels: Vec<usize> = Vec::new();
+    for entry in entries {
+        while levels.last().is_some_and(|level| *level > entry.level) {
+            html.push_str("</li>\n</ul>\n");
+            levels.pop();
+        }
+        if levels.last().is_some_and(|level| *level == entry.level) {
+            html.push_str("</li>\n");
+        } else {
+            html.push_str("<ul>\n");
+            levels.push(entry.level);
+        }
+        html.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a>",
+            escape_html(&entry.id),
+            escape_html(&entry.title)
+        ));
+    }
+    for _ in levels {
+        html.push_str("</li>\n</ul>\n");
+    }
+    html.push_str("</details>\n");
+    Some(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::{process_markdown, RenderOptions};
+
+    #[test]
+    fn markdown_heading_anchors() {
+        let html = process_markdown(
+            "# Install\n\n## Linux & macOS\n\n## Linux & macOS\n\n### `cargo` setup\n\n# Usage",
+            "a",
+            "r",
+            &RenderOptions::default(),
+        );
+        let (html, entries) = add_heading_anchors(&html);
+
+        let ids: Vec<&str> = entries.iter().map(|entry| entry.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            [
+                "user-content-install",
+                "user-content-linux--macos",
+                "user-content-linux--macos-1",
+                "user-content-cargo-setup",
+                "user-content-usage"
+            ]
+        );
+        assert!(html.starts_with(
+            "<h1 id=\"user-content-install\"><a href=\"#user-content-install\" aria-hidden=\"true\" class=\"anchor\"></a>Install</h1>"
+        ));
+        assert_eq!(
+            html.matches("id=\"user-content-linux--macos-1\"").count(),
+            1
+        );
+    }
+
+    #[test]
+    fn github_fallback_heading_anchors() {
+        let html = "<div class=\"markdown-heading\"><h2 class=\"heading-element\">Getting Started</h2><a id=\"user-content-getting-started\" class=\"anchor\" href=\"#getting-started\"></a></div><p><sup><a href=\"#user-content-fn-1\" id=\"user-content-fnref-1\">1</a></sup></p><h3 id=\"user-content-custom\">Custom</h3>";
+        let (html, entries) = add_heading_anchors(html);
+
+        assert_eq!(
+            html,
+            "<div class=\"markdown-heading\"><h2 class=\"heading-element\" id=\"user-content-getting-started\">Getting Started</h2><a class=\"anchor\" href=\"#user-content-getting-started\"></a></div><p><sup><a href=\"#user-content-fn-1\" id=\"user-content-fnref-1\">1</a></sup></p><h3 id=\"user-content-custom\">Custom</h3>"
+        );
+        assert_eq!(entries[1].id, "user-content-custom");
+    }
+
+    #[test]
+    fn ids_stay_clear_of_the_template() {
+        let html = "<h1>Content</h1><h2>Index</h2><p><a href=\"#content\">up</a> <a href=\"/m/a/r/wiki/Other#index\">there</a> <a href=\"https://example.com/#top\">out</a></p><a name=\"main\"></a>";
+        let (html, entries) = add_heading_anchors(html);
+
+        assert_eq!(
+            html,
+            "<h1 id=\"user-content-content\">Content</h1><h2 id=\"user-content-index\">Index</h2><p><a href=\"#user-content-content\">up</a> <a href=\"/m/a/r/wiki/Other#user-content-index\">there</a> <a href=\"https://example.com/#top\">out</a></p><a name=\"user-content-main\"></a>"
+        );
+        assert_eq!(entries[0].id, "user-content-content");
+    }
+
+    #[test]
+    fn nested_toc() {
+        let entry = |level, id: &str| TocEntry {
+            level,
+            id: id.to_string(),
+            title: id.to_uppercase(),
+        };
+
+        assert_eq!(render_toc(&[entry(2, "a")]), None);
+        assert_eq!(
+            render_toc(&[entry(2, "a"), entry(3, "b"), entry(3, "c"), entry(2, "d<")]).unwrap(),
+            "<details id=\"toc\"><summary>Contents</summary>\n<ul>\n<li><a href=\"#a\">A</a><ul>\n<li><a href=\"#b\">B</a></li>\n<li><a href=\"#c\">C</a></li>\n</ul>\n</li>\n<li><a href=\"#d&lt;\">D&lt;</a></li>\n</ul>\n</details>\n"
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use std::path::Path;
+use std::time::Duration;
+
+use percent_encoding::percent_decode_str;
+use reqwest::Client;
+use rocket::{info, warn};
+use tokio::sync::Mutex;
+use tokio::time::MissedTickBehavior;
+
+use crate::config::Config;
+use crate::content_store::Snapshot;
+use crate::retrieval::{
+    retrieve_source_file_paced, retrieve_wiki_page_listing_paced, retrieve_wiki_sitemap_index,
+    ContentError,
+};
+use crate::search::SearchIndex;
+use crate::{content_to_html, wiki_page_name, wiki_page_title};
+
+/// Pause after GitHub says we are making too many requests.
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+async fn back_off() {
+    warn!(
+        "warm-up: rate limited, pausing for {}s",
+        RATE_LIMIT_BACKOFF.as_secs()
+    );
+    tokio::time::sleep(RATE_LIMIT_BACKOFF).await;
+}
+
+/// Wikis named in a seed list, in order and without duplicates.
+///
+/// Each line is `account/repository`, a GitHub or mirror URL of the wiki or one of its
+/// pages, or a sitemap `<loc>` with such a URL. Blank lines and `#` comments are skipped.
+pub fn parse_seed(seed: &str) -> Vec<(String, String)> {
+    let mut wikis: Vec<(String, String)> = Vec::new();
+    for line in seed.lines() {
+        let line = match line.split_once("<loc>") {
+            Some((_, loc)) => loc.split("</loc>").next().unwrap_or(loc),
+            None => line,
+        }
+        .trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('<') {
+            continue;
+        }
+
+        let path = match line.split_once("://") {
+            Some((_, rest)) => rest.split_once('/').map_or("", |(_, path)| path),
+            None => line,
+        };
+        let path = path.strip_prefix("m/").unwrap_or(path);
+        let mut segments = path.split('/');
+        let (Some(account), Some(repository)) = (segments.next(), segments.next()) else {
+            continue;
+        };
+        if account.is_empty() || repository.is_empty() {
+            continue;
+        }
+
+        let wiki = (account.to_string(), repository.to_string());
+        if !wikis.contains(&wiki) {
+            wikis.push(wiki);
+        }
+    }
+    wikis
+}
+
+/// Read a seed list from a file, or fetch it if `seed` is a URL.
+pub async fn load_seed(seed: &str, client: &Client) -> Result<Vec<(String, String)>, String> {
+    let text = if seed.starts_with("https://") || seed.starts_with("http://") {
+        client
+            .get(seed)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|error| error.to_string())?
+            .text()
+            .await
+            .map_err(|error| error.to_string())?
+    } else {
+        std::fs::read_to_string(seed).map_err(|error| error.to_string())?
+    };
+    Ok(parse_seed(&text))
+}
+
+#[derive(Debug, Default)]
+pub struct WarmUpStats {
+    pub wikis: usize,
+    pub pages: usize,
+    pub failures: usize,
+}
+
+/// Fetches every page of some wikis ahead of crawlers, at a polite pace.
+pub struct WarmUp<'a> {
+    pub client: &'a Client,
+    pub config: &'a Config,
+    /// Index to add the fetched pages to.
+    pub search: Option<&'a SearchIndex>,
+    /// Directory to save each wiki's sitemap in, as `<account>/<repository>/sitemap.xml`.
+    pub sitemap_dir: Option<&'a Path>,
+}
+
+impl WarmUp<'_> {
+    fn save_sitemap(&self, account: &str, repository: &str, xml: &str) -> std::io::Result<()> {
+        let Some(sitemap_dir) = self.sitemap_dir else {
+            return Ok(());
+        };
+        let dir = sitemap_dir.join(account).join(repository);
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("sitemap.xml"), xml)
+    }
+
+    /// Fetch every page of `wikis`, logging progress.
+    ///
+    /// Pages are kept in the content store, if there is one, and served from there until
+    /// the next warm-up instead of being fetched again.
+    pub async fn run(&self, wikis: &[(String, String)]) -> WarmUpStats {
+        let per_request = Duration::from_secs(60) / self.config.warm_up_requests_per_minute.max(1);
+        let mut interval = tokio::time::interval(per_request);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let interval = &Mutex::new(interval);
+        // Awaited before every request to GitHub, however many one page takes.
+        let pace = || async move {
+            interval.lock().await.tick().await;
+        };
+        let options = self.config.render_options();
+        let store = self.config.content_store();
+        let mut stats = WarmUpStats::default();
+
+        for (number, (account, repository)) in wikis.iter().enumerate() {
+            let progress = format!(
+                "warm-up [{}/{}] {account}/{repository}",
+                number + 1,
+                wikis.len()
+            );
+
+            let listing = match retrieve_wiki_page_listing_paced(
+                account,
+                repository,
+                self.client,
+                pace,
+            )
+            .await
+            {
+                Ok(listing) => listing,
+                Err(error) => {
+                    warn!("{progress}: {error}");
+                    stats.failures += 1;
+                    if error == ContentError::TooMayRequests {
+                        back_off().await;
+                    }
+                    continue;
+                }
+            };
+            // Built from the listing just fetched, and remembers the wiki for the sitemap index.
+            match retrieve_wiki_sitemap_index(
+                account,
+                repository,
+                self.client,
+                self.config.base_url(),
+            )
+            .await
+            {
+                Ok(xml) => {
+                    if let Err(error) = self.save_sitemap(account, repository, &xml) {
+                        warn!("{progress}: could not save sitemap: {error}");
+                    }
+                }
+                Err(error) => warn!("{progress}: no sitemap: {error}"),
+            }
+
+            let mut pages = 0;
+            for page in &listing.pages {
+                // Named as in mirror URLs, like the pages the mirror fetches and indexes.
+                let page = percent_decode_str(&page.slug).decode_utf8_lossy();
+                match retrieve_source_file_paced(account, repository, &page, self.client, pace)
+                    .await
+                {
+                    Ok(content) => {
+                        pages += 1;
+                        if store.is_none() && self.search.is_none() {
+                            continue;
+                        }
+                        let format = content.format();
+                        let source = content.source().to_string();
+                        let html = content_to_html(content, account, repository, &options);
+                        if let Some(search) = self.search {
+                            let title = wiki_page_name(account, repository, &page);
+                            search.index_page(account, repository, &page, &title, &html);
+                        }
+                        if let Some(store) = &store {
+                            let title = wiki_page_title(account, repository, &page);
+                            let snapshot = Snapshot {
+                                warmed: true,
+                                ..Snapshot::new(title, format, source, html)
+                            };
+                            let saved = store
+                                .clone()
+                                .save_in_background(account, repository, &page, snapshot);
+                            // Only fails if saving panicked, which has been reported already.
+                            let _ = saved.await;
+                        }
+                    }
+                    Err(error) => {
+                        stats.failures += 1;
+                        // The rest of this wiki would be refused too.
+                        if error == ContentError::TooMayRequests {
+                            back_off().await;
+                            break;
+                        }
+                    }
+                }
+            }
+
+            info!("{progress}: {pages}/{} pages", listing.pages.len());
+            stats.wikis += 1;
+            stats.pages += pages;
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seed_lists() {
+        let seed = "\
+# Popular wikis
+nelsonjchen/github-wiki-test
+https://github.com/rust-lang/rust/wiki/Home
+
+<url><loc>https://github-wiki-see.page/m/rust-lang/book/wiki/Note</loc></url>
+  <loc>https://github-wiki-see.page/m/a/r/wiki</loc>
+https://github-wiki-see.page/
+just-an-account
+";
+
+        assert_eq!(
+            parse_seed(seed),
+            [
+                ("nelsonjchen".to_string(), "github-wiki-test".to_string()),
+                ("rust-lang".to_string(), "rust".to_string()),
+                ("rust-lang".to_string(), "book".to_string()),
+                ("a".to_string(), "r".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use rocket::serde::Serialize;
+
+/// A page of a GitHub wiki, as linked from the wiki's page listing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct WikiPageRef {
+    pub account: String,
+    pub repository: String,
+    /// Page name as it appears in the URL, e.g. `Getting-Started`.
+    pub slug: String,
+    pub title: String,
+}
+
+impl WikiPageRef {
+    /// Parse a GitHub wiki page link such as `/account/repository/wiki/Page`.
+    ///
+    /// Returns `None` for anything that is not a page of a wiki, including
+    /// special pages like `_history`, `_new` and `_pages`.
+    pub fn from_href(href: &str, title: &str) -> Option<Self> {
+        let path = href.strip_prefix("https://github.com").unwrap_or(href);
+        let mut segments = path.strip_prefix('/')?.split('/');
+        let account = segments.next().filter(|s| !s.is_empty())?;
+        let repository = segments.next().filter(|s| !s.is_empty())?;
+        if segments.next()? != "wiki" {
+            return None;
+        }
+        let slug = match (segments.next(), segments.next()) {
+            (None, _) => "Home",
+            (Some(slug), None) => slug,
+            (Some(_), Some(_)) => return None,
+        };
+        if slug.is_empty() || slug.starts_with('_') || slug.contains(['?', '#']) {
+            return None;
+        }
+
+        let title = title.trim();
+        Some(Self {
+            account: account.to_string(),
+            repository: repository.to_string(),
+            slug: slug.to_string(),
+            title: if title.is_empty() {
+                slug.replace('-', " ")
+            } else {
+                title.to_string()
+            },
+        })
+    }
+
+    pub fn is_home(&self) -> bool {
+        self.slug == "Home"
+    }
+
+    pub fn belongs_to(&self, account: &str, repository: &str) -> bool {
+        self.account.eq_ignore_ascii_case(account)
+            && self.repository.eq_ignore_ascii_case(repository)
+    }
+
+    /// Path of the page on github.com.
+    pub fn github_path(&self) -> String {
+        if self.is_home() {
+            format!("/{}/{}/wiki", self.account, self.repository)
+        } else {
+            format!("/{}/{}/wiki/{}", self.account, self.repository, self.slug)
+        }
+    }
+
+    /// Path of the page on this mirror.
+    pub fn mirror_path(&self) -> String {
+        format!("/m{}", self.github_path())
+    }
+}
+
+/// Links from a page to its neighbours in the wiki's page listing.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PageNavigation {
+    pub previous: Option<WikiPageRef>,
+    pub next: Option<WikiPageRef>,
+    /// Other pages whose titles share words with this one.
+    pub related: Vec<WikiPageRef>,
+}
+
+const MAX_RELATED_PAGES: usize = 5;
+
+fn title_words(title: &str) -> Vec<String> {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Previous, next and related pages for `slug` (encoded or not), given the sorted pages of its wiki.
+pub fn page_navigation(pages: &[WikiPageRef], slug: &str) -> Option<PageNavigation> {
+    let index = pages.iter().position(|page| {
+        page.slug == slug
+            || percent_encoding::percent_decode_str(&page.slug).decode_utf8_lossy() == slug
+    })?;
+    let previous = index.checked_sub(1).and_then(|index| pages.get(index));
+    let next = pages.get(index + 1);
+
+    let words = title_words(&pages[index].title);
+    let mut related: Vec<(usize, &WikiPageRef)> = pages
+        .iter()
+        .enumerate()
+        // Not the page itself, nor the previous and next pages already linked.
+        .filter(|(other, _)| other.abs_diff(index) > 1)
+        .map(|(_, page)| {
+            let shared = title_words(&page.title)
+                .iter()
+                .filter(|word| words.contains(word))
+                .count();
+            (shared, page)
+        })
+        .filter(|(shared, _)| *shared > 0)
+        .collect();
+    // Stable, so equally related pages stay in listing order.
+    related.sort_by_key(|(shared, _)| std::cmp::Reverse(*shared));
+
+    Some(PageNavigation {
+        previous: previous.cloned(),
+        next: next.cloned(),
+        related: related
+            .into_iter()
+            .take(MAX_RELATED_PAGES)
+            .map(|(_, page)| page.clone())
+            .collect(),
+    })
+}
+
+/// Drop duplicate pages and sort them by title, with Home first like GitHub's sidebar.
+pub fn sort_and_dedup(mut pages: Vec<WikiPageRef>) -> Vec<WikiPageRef> {
+    pages.sort_by_cached_key(|page| {
+        (
+            !page.is_home(),
+            page.title.to_lowercase(),
+            page.slug.clone(),
+        )
+    });
+    let mut seen = std::collections::HashSet::new();
+    pages.retain(|page| seen.insert(page.slug.clone()));
+    pages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hrefs() {
+        let home = WikiPageRef::from_href("/a/r/wiki", " Home\n").unwrap();
+        assert_eq!(home.slug, "Home");
+        assert_eq!(home.title, "Home");
+        assert_eq!(home.mirror_path(), "/m/a/r/wiki");
+
+        let page = WikiPageRef::from_href("https://github.com/A/R/wiki/What's-up", "").unwrap();
+        assert_eq!(page.slug, "What's-up");
+        assert_eq!(page.title, "What's up");
+        assert!(page.belongs_to("a", "r"));
+        assert_eq!(page.github_path(), "/A/R/wiki/What's-up");
+
+        for href in [
+            "/a/r/wiki/",
+            "/a/r/wiki/_new",
+            "/a/r/wiki/_pages?page=2",
+            "/a/r/wiki/Home/_history",
+            "/a/r/wikis",
+            "/a/r",
+            "#wiki-pages-box",
+            "https://example.com/a/r/wiki/Page",
+        ] {
+            assert_eq!(WikiPageRef::from_href(href, "x"), None, "{href}");
+        }
+    }
+
+    #[test]
+    fn navigation() {
+        let pages: Vec<WikiPageRef> = [
+            "Home",
+            "Install-on-Linux",
+            "Install-on-Windows",
+            "Linux-Troubleshooting",
+            "Usage",
+            "Windows-FAQ",
+        ]
+        .iter()
+        .map(|slug| WikiPageRef::from_href(&format!("/a/r/wiki/{slug}"), "").unwrap())
+        .collect();
+        let slugs = |pages: &[WikiPageRef]| -> Vec<String> {
+            pages.iter().map(|page| page.slug.clone()).collect()
+        };
+
+        let navigation = page_navigation(&pages, "Install-on-Windows").unwrap();
+        assert_eq!(navigation.previous.unwrap().slug, "Install-on-Linux");
+        assert_eq!(navigation.next.unwrap().slug, "Linux-Troubleshooting");
+        assert_eq!(slugs(&navigation.related), ["Windows-FAQ"]);
+
+        let navigation = page_navigation(&pages, "Home").unwrap();
+        assert_eq!(navigation.previous, None);
+        assert_eq!(navigation.next.unwrap().slug, "Install-on-Linux");
+        assert!(navigation.related.is_empty());
+
+        let navigation = page_navigation(&pages, "Windows-FAQ").unwrap();
+        assert_eq!(navigation.next, None);
+        assert_eq!(slugs(&navigation.related), ["Install-on-Windows"]);
+
+        assert_eq!(page_navigation(&pages, "Missing"), None);
+    }
+
+    #[test]
+    fn sorts_home_first_and_dedups() {
+        let pages = sort_and_dedup(vec![
+            WikiPageRef::from_href("/a/r/wiki/zeta", "zeta").unwrap(),
+            WikiPageRef::from_href("/a/r/wiki/Alpha", "Alpha").unwrap(),
+            WikiPageRef::from_href("/a/r/wiki", "Home").unwrap(),
+            WikiPageRef::from_href("/a/r/wiki/Alpha", "Alpha").unwrap(),
+        ]);
+        let slugs: Vec<&str> = pages.iter().map(|page| page.slug.as_str()).collect();
+        assert_eq!(slugs, ["Home", "Alpha", "zeta"]);
+    }
+}